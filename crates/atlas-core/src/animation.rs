@@ -0,0 +1,290 @@
+//! Animated playback of recordings, where the paths grow frame by frame across the rendered map.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use annotate_celeste_map::ColorMode;
+use anyhow::{bail, Context, Result};
+use tiny_skia::{Color, FillRule, Paint, PathBuilder, Pixmap, Stroke, Transform};
+
+use crate::colors;
+use crate::render::{self, OutputFormat, RenderOptions, RenderState};
+
+/// Frames per second of the game, used for the frame delays
+const GAME_FPS: u16 = 60;
+
+/// Renders the map once and saves an animation of the recordings being played back on it,
+/// advancing `frame_stride` game frames per animation frame.
+///
+/// Paths are colored by state for [`ColorMode::State`], in the chosen color for
+/// [`ColorMode::Color`] and with one color per recording otherwise.
+/// The player position is always marked in the color of its recording.
+#[allow(clippy::too_many_arguments)]
+pub fn render_animation(
+    state: &mut RenderState,
+    map_bin: &str,
+    name: &str,
+    recordings: &[u32],
+    options: RenderOptions,
+    frame_stride: u32,
+    format: OutputFormat,
+    out_path: &Path,
+    on_status_update: &impl Fn(String),
+) -> Result<()> {
+    let (result, _map) =
+        render::render_background(state, map_bin, name, recordings, &options, on_status_update)?;
+    let background = result.image;
+    let offset = (
+        result.bounds.position.x as f32,
+        result.bounds.position.y as f32,
+    );
+
+    let mut paths = Vec::new();
+    for &recording in recordings {
+        let mut path = Vec::new();
+        for item in state.physics_inspector.position_log(recording)? {
+            let item = item?;
+            let player_state = item.flags.split(' ').next().unwrap_or_default().to_owned();
+            path.push((item.x - offset.0, item.y - offset.1, player_state));
+        }
+        paths.push(path);
+    }
+
+    let total_frames = paths.iter().map(Vec::len).max().unwrap_or(0);
+    if total_frames == 0 {
+        bail!("the recordings of {name} are empty");
+    }
+    let Ok(frame_stride) = u16::try_from(frame_stride.max(1)) else {
+        bail!("frame stride {frame_stride} is too large, at most 65535");
+    };
+    let mut frames: Vec<usize> = (0..total_frames).step_by(frame_stride as usize).collect();
+    if frames.last() != Some(&(total_frames - 1)) {
+        frames.push(total_frames - 1);
+    }
+
+    let mut encoder = Encoder::new(
+        format,
+        out_path,
+        background.width(),
+        background.height(),
+        frames.len() as u32,
+        frame_stride,
+    )?;
+
+    let line_settings = options.line_settings;
+    let stroke = Stroke {
+        width: line_settings.width,
+        line_cap: tiny_skia::LineCap::Round,
+        line_join: tiny_skia::LineJoin::Round,
+        ..Default::default()
+    };
+
+    let mut trail = background;
+    let mut drawn_until = vec![0; paths.len()];
+    for (i, &frame) in frames.iter().enumerate() {
+        if i % 10 == 0 {
+            on_status_update(format!("Encoding frame [{}/{}]", i + 1, frames.len()));
+        }
+
+        for (n, path) in paths.iter().enumerate() {
+            if path.is_empty() {
+                continue;
+            }
+            let until = frame.min(path.len() - 1);
+            draw_trail(
+                &mut trail,
+                &path[drawn_until[n]..=until.max(drawn_until[n])],
+                |player_state| match line_settings.color_mode {
+                    ColorMode::State => state_color(player_state),
+                    ColorMode::Color([r, g, b, a]) => Color::from_rgba8(r, g, b, a),
+                    ColorMode::Random => {
+                        let [r, g, b] = colors::random_color(n);
+                        Color::from_rgba8(r, g, b, colors::RANDOM_ALPHA)
+                    }
+                    ColorMode::Gradient => recording_color(n),
+                },
+                line_settings.anti_alias,
+                &stroke,
+            );
+            drawn_until[n] = until;
+        }
+
+        let mut image = trail.clone();
+        for (n, path) in paths.iter().enumerate() {
+            let Some((x, y, _)) = path.get(frame).or(path.last()) else {
+                continue;
+            };
+            draw_player(&mut image, *x, *y, recording_color(n), line_settings.width);
+        }
+
+        encoder.write_frame(&image, i)?;
+    }
+    encoder.finish()
+}
+
+fn draw_trail(
+    image: &mut Pixmap,
+    points: &[(f32, f32, String)],
+    color: impl Fn(&str) -> Color,
+    anti_alias: bool,
+    stroke: &Stroke,
+) {
+    let mut paint = Paint {
+        anti_alias,
+        ..Default::default()
+    };
+
+    // one path per run of points with the same color
+    let mut start = 0;
+    while start + 1 < points.len() {
+        let run_color = color(&points[start].2);
+        let mut pb = PathBuilder::new();
+        pb.move_to(points[start].0, points[start].1);
+
+        let mut end = start + 1;
+        loop {
+            pb.line_to(points[end].0, points[end].1);
+            if end + 1 == points.len() || color(&points[end].2) != run_color {
+                break;
+            }
+            end += 1;
+        }
+
+        if let Some(path) = pb.finish() {
+            paint.set_color(run_color);
+            image.stroke_path(&path, &paint, stroke, Transform::identity(), None);
+        }
+        start = end;
+    }
+}
+
+fn draw_player(image: &mut Pixmap, x: f32, y: f32, color: Color, line_width: f32) {
+    let Some(circle) = PathBuilder::from_circle(x, y, (line_width * 2.0).max(4.0)) else {
+        return;
+    };
+    let mut paint = Paint {
+        anti_alias: true,
+        ..Default::default()
+    };
+    paint.set_color(color);
+    image.fill_path(
+        &circle,
+        &paint,
+        FillRule::Winding,
+        Transform::identity(),
+        None,
+    );
+    paint.set_color(Color::WHITE);
+    let stroke = Stroke {
+        width: 1.5,
+        ..Default::default()
+    };
+    image.stroke_path(&circle, &paint, &stroke, Transform::identity(), None);
+}
+
+fn state_color(player_state: &str) -> Color {
+    let [r, g, b] = colors::state_color(player_state);
+    Color::from_rgba8(r, g, b, 255)
+}
+
+fn recording_color(n: usize) -> Color {
+    let [r, g, b] = colors::comparison_color(n);
+    Color::from_rgba8(r, g, b, 255)
+}
+
+enum Encoder<'a> {
+    Gif(gif::Encoder<BufWriter<File>>, u16),
+    Apng(png::Writer<BufWriter<File>>),
+    Frames(&'a Path),
+}
+
+impl<'a> Encoder<'a> {
+    fn new(
+        format: OutputFormat,
+        path: &'a Path,
+        width: u32,
+        height: u32,
+        frame_count: u32,
+        frame_stride: u16,
+    ) -> Result<Self> {
+        let create = || {
+            File::create(path)
+                .map(BufWriter::new)
+                .with_context(|| format!("failed to create {}", path.display()))
+        };
+
+        Ok(match format {
+            OutputFormat::Gif => {
+                let (width, height) = gif_size(width, height)?;
+                let mut encoder = gif::Encoder::new(create()?, width, height, &[])?;
+                encoder.set_repeat(gif::Repeat::Infinite)?;
+                // GIF delays are in hundredths of a second
+                let delay = (frame_stride as f32 * 100.0 / GAME_FPS as f32).round() as u16;
+                Encoder::Gif(encoder, delay.max(2))
+            }
+            OutputFormat::Apng => {
+                let mut encoder = png::Encoder::new(create()?, width, height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_compression(png::Compression::Fast);
+                encoder.set_animated(frame_count, 0)?;
+                encoder.set_frame_delay(frame_stride, GAME_FPS)?;
+                Encoder::Apng(encoder.write_header()?)
+            }
+            OutputFormat::Frames => {
+                std::fs::create_dir_all(path)?;
+                Encoder::Frames(path)
+            }
+            OutputFormat::Png | OutputFormat::Svg => {
+                bail!("{} is not an animated format", format.name())
+            }
+        })
+    }
+
+    fn write_frame(&mut self, image: &Pixmap, i: usize) -> Result<()> {
+        match self {
+            Encoder::Gif(encoder, delay) => {
+                let (width, height) = gif_size(image.width(), image.height())?;
+                let mut rgba = demultiplied_rgba(image);
+                let mut frame = gif::Frame::from_rgba_speed(width, height, &mut rgba, 10);
+                frame.delay = *delay;
+                encoder.write_frame(&frame)?;
+            }
+            Encoder::Apng(writer) => writer.write_image_data(&demultiplied_rgba(image))?,
+            Encoder::Frames(dir) => image.save_png(dir.join(format!("{i:05}.png")))?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            Encoder::Gif(encoder, _) => {
+                encoder.into_inner()?;
+            }
+            Encoder::Apng(writer) => writer.finish()?,
+            Encoder::Frames(_) => {}
+        }
+        Ok(())
+    }
+}
+
+fn gif_size(width: u32, height: u32) -> Result<(u16, u16)> {
+    match (u16::try_from(width), u16::try_from(height)) {
+        (Ok(width), Ok(height)) => Ok((width, height)),
+        _ => bail!(
+            "map is {width}x{height} px, but GIF is limited to 65535 px, use a region or APNG"
+        ),
+    }
+}
+
+fn demultiplied_rgba(image: &Pixmap) -> Vec<u8> {
+    image
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect()
+}
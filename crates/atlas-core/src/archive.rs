@@ -0,0 +1,195 @@
+//! Atlas-owned archive of pinned recordings, which survives CCT rotating out or deleting its
+//! recent recordings.
+//!
+//! The archive uses the same file layout as CCT's `recent-recordings` folder, so it can be read
+//! through a [`PhysicsInspector`]. Indices in the archive don't shift when recordings are added or removed.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use celesteloader::cct_physics_inspector::{CCTRoomLayout, PhysicsInspector};
+
+use crate::labels::{Label, LabelIndex};
+use crate::provenance::{Provenance, ProvenanceIndex};
+use crate::sidecar::recording_key;
+
+pub(crate) const ROOM_LAYOUT_SUFFIX: &str = "_room-layout.json";
+pub(crate) const POSITION_LOG_SUFFIX: &str = "_position-log.txt";
+
+#[derive(Clone)]
+pub struct Archive {
+    physics_inspector: PhysicsInspector,
+}
+
+impl Archive {
+    pub fn new(dir: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create archive at {}", dir.display()))?;
+        Ok(Archive {
+            physics_inspector: PhysicsInspector {
+                recent_recordings: dir,
+            },
+        })
+    }
+
+    /// The archive in Atlas' data directory, shared between Celeste installations.
+    pub fn open_default() -> Result<Self> {
+        let data_dir = dirs::data_dir().context("could not find data dir")?;
+        Archive::new(data_dir.join("Atlas").join("archive"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.physics_inspector.recent_recordings
+    }
+
+    /// Reads the archived recordings like CCT's recent recordings.
+    pub fn physics_inspector(&self) -> &PhysicsInspector {
+        &self.physics_inspector
+    }
+
+    /// [`recording_key`]s of all archived recordings.
+    pub fn keys(&self) -> Result<HashSet<String>> {
+        Ok(self
+            .physics_inspector
+            .recent_recordings()?
+            .iter()
+            .map(|(_, layout)| recording_key(layout))
+            .collect())
+    }
+
+    /// Index of the archived copy of the recording with the given key.
+    pub fn find(&self, key: &str) -> Result<Option<u32>> {
+        Ok(self
+            .physics_inspector
+            .recent_recordings()?
+            .into_iter()
+            .find(|(_, layout)| recording_key(layout) == key)
+            .map(|(i, _)| i))
+    }
+
+    fn next_index(&self) -> Result<u32> {
+        let mut next = 0;
+        for entry in std::fs::read_dir(self.dir())? {
+            let name = entry?.file_name();
+            let index = name.to_str().and_then(|name| {
+                let index = name
+                    .strip_suffix(ROOM_LAYOUT_SUFFIX)
+                    .or_else(|| name.strip_suffix(POSITION_LOG_SUFFIX))?;
+                index.parse::<u32>().ok()
+            });
+            if let Some(index) = index {
+                next = next.max(index + 1);
+            }
+        }
+        Ok(next)
+    }
+
+    /// Copies recording `index` of `source` into the archive, together with its label and
+    /// provenance. Returns the index in the archive, which is the existing one if it was already pinned.
+    pub fn pin(&self, source: &PhysicsInspector, index: u32) -> Result<u32> {
+        let read = |suffix: &str| {
+            let path = source.recent_recordings.join(format!("{index}{suffix}"));
+            std::fs::read(&path).with_context(|| format!("failed to read {}", path.display()))
+        };
+        let room_layout = read(ROOM_LAYOUT_SUFFIX)?;
+        let position_log = read(POSITION_LOG_SUFFIX)?;
+
+        let key = recording_key(&CCTRoomLayout::from_reader(room_layout.as_slice())?);
+        let label = LabelIndex::load(source)?.get(&key).cloned();
+        let provenance = ProvenanceIndex::load(source)?.get(&key).cloned();
+
+        self.add(
+            &room_layout,
+            &position_log,
+            label.unwrap_or_default(),
+            provenance,
+        )
+    }
+
+    /// Adds a recording from the contents of its CCT files. Returns the index in the archive,
+    /// which is the existing one if the recording was already archived.
+    pub fn add(
+        &self,
+        room_layout: &[u8],
+        position_log: &[u8],
+        label: Label,
+        provenance: Option<Provenance>,
+    ) -> Result<u32> {
+        let layout = CCTRoomLayout::from_reader(room_layout).context("invalid CCT room layout")?;
+        let key = recording_key(&layout);
+        if let Some(existing) = self.find(&key)? {
+            return Ok(existing);
+        }
+
+        let index = self.next_index()?;
+        // the room layout is written last, as it is what makes the recording show up
+        for (suffix, data) in [
+            (POSITION_LOG_SUFFIX, position_log),
+            (ROOM_LAYOUT_SUFFIX, room_layout),
+        ] {
+            let path = self.dir().join(format!("{index}{suffix}"));
+            std::fs::write(&path, data)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+
+        let keys = self.keys()?;
+        if !label.is_empty() {
+            let mut labels = LabelIndex::load(self.physics_inspector())?;
+            labels.set(key.clone(), label);
+            labels.save(self.physics_inspector(), &keys)?;
+        }
+        if let Some(provenance) = provenance {
+            let mut index = ProvenanceIndex::load(self.physics_inspector())?;
+            index.insert(key, provenance);
+            index.save(self.physics_inspector(), &keys)?;
+        }
+
+        Ok(index)
+    }
+
+    /// Removes a recording from the archive.
+    pub fn unpin(&self, index: u32) -> Result<()> {
+        for suffix in [ROOM_LAYOUT_SUFFIX, POSITION_LOG_SUFFIX] {
+            let path = self.dir().join(format!("{index}{suffix}"));
+            if path.exists() {
+                std::fs::remove_file(&path)
+                    .with_context(|| format!("failed to remove {}", path.display()))?;
+            }
+        }
+
+        let keys = self.keys()?;
+        LabelIndex::load(self.physics_inspector())?.save(self.physics_inspector(), &keys)?;
+        ProvenanceIndex::load(self.physics_inspector())?.save(self.physics_inspector(), &keys)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn pin_unpin() -> Result<()> {
+    let dir = crate::test_utils::temp_dir();
+    let recent = PhysicsInspector {
+        recent_recordings: dir.path().join("recent"),
+    };
+    for i in [0, 1] {
+        crate::test_utils::write_recording(&recent, i, i, "")?;
+    }
+
+    let archive = Archive::new(dir.path().join("archive"))?;
+    assert_eq!(archive.pin(&recent, 1)?, 0);
+    assert_eq!(archive.pin(&recent, 0)?, 1);
+    assert_eq!(
+        archive.pin(&recent, 1)?,
+        0,
+        "pinning twice keeps the existing copy"
+    );
+
+    archive.unpin(0)?;
+    assert_eq!(
+        archive.pin(&recent, 1)?,
+        2,
+        "indices of remaining recordings don't shift"
+    );
+    assert_eq!(archive.physics_inspector().recent_recordings()?.len(), 2);
+    Ok(())
+}
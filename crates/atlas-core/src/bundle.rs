@@ -0,0 +1,150 @@
+//! `.atlasrec` bundles for sharing recordings: a zip file with the CCT files of each recording
+//! and a manifest with their labels and provenance.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use celesteloader::cct_physics_inspector::PhysicsInspector;
+use serde::{Deserialize, Serialize};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::archive::{Archive, POSITION_LOG_SUFFIX, ROOM_LAYOUT_SUFFIX};
+use crate::labels::{Label, LabelIndex};
+use crate::provenance::{Provenance, ProvenanceIndex};
+use crate::sidecar::recording_key;
+
+pub const EXTENSION: &str = "atlasrec";
+
+const MANIFEST: &str = "manifest.json";
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    recordings: Vec<ManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ManifestEntry {
+    /// Prefix of the CCT files of the recording in the bundle
+    index: u32,
+    #[serde(default)]
+    label: Label,
+    provenance: Option<Provenance>,
+}
+
+/// Writes the recordings, each given by the [`PhysicsInspector`] it is read from and its index
+/// there, into a bundle at `path`.
+pub fn export_bundle(path: &Path, recordings: &[(&PhysicsInspector, u32)]) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut manifest = Manifest {
+        version: VERSION,
+        recordings: Vec::with_capacity(recordings.len()),
+    };
+    for (n, &(physics_inspector, index)) in recordings.iter().enumerate() {
+        let n = n as u32;
+        for suffix in [ROOM_LAYOUT_SUFFIX, POSITION_LOG_SUFFIX] {
+            let source = physics_inspector
+                .recent_recordings
+                .join(format!("{index}{suffix}"));
+            let data = std::fs::read(&source)
+                .with_context(|| format!("failed to read {}", source.display()))?;
+            zip.start_file(format!("{n}{suffix}"), options)?;
+            zip.write_all(&data)?;
+        }
+
+        let key = recording_key(&physics_inspector.room_layout(index)?);
+        manifest.recordings.push(ManifestEntry {
+            index: n,
+            label: LabelIndex::load(physics_inspector)?
+                .get(&key)
+                .cloned()
+                .unwrap_or_default(),
+            provenance: ProvenanceIndex::load(physics_inspector)?.get(&key).cloned(),
+        });
+    }
+
+    zip.start_file(MANIFEST, options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    zip.finish()?;
+
+    Ok(())
+}
+
+/// Adds the recordings of the bundle at `path` to the archive, skipping ones that are already
+/// in it. Returns their indices in the archive.
+pub fn import_bundle(path: &Path, archive: &Archive) -> Result<Vec<u32>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut zip = ZipArchive::new(file)
+        .with_context(|| format!("{} is not a recording bundle", path.display()))?;
+
+    let manifest: Manifest = serde_json::from_slice(&read_entry(&mut zip, MANIFEST)?)
+        .context("invalid bundle manifest")?;
+    if manifest.version > VERSION {
+        bail!(
+            "{} was exported by a newer version of Atlas, please update",
+            path.display()
+        );
+    }
+
+    let mut indices = Vec::with_capacity(manifest.recordings.len());
+    for entry in manifest.recordings {
+        let index = entry.index;
+        let room_layout = read_entry(&mut zip, &format!("{index}{ROOM_LAYOUT_SUFFIX}"))?;
+        let position_log = read_entry(&mut zip, &format!("{index}{POSITION_LOG_SUFFIX}"))?;
+        let index = archive.add(&room_layout, &position_log, entry.label, entry.provenance)?;
+        indices.push(index);
+    }
+
+    Ok(indices)
+}
+
+fn read_entry(zip: &mut ZipArchive<File>, name: &str) -> Result<Vec<u8>> {
+    let mut entry = zip
+        .by_name(name)
+        .with_context(|| format!("bundle is missing {name}"))?;
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[test]
+fn roundtrip() -> Result<()> {
+    let dir = crate::test_utils::temp_dir();
+    let recent = PhysicsInspector {
+        recent_recordings: dir.path().join("recent"),
+    };
+    crate::test_utils::write_recording(&recent, 3, 0, "log")?;
+    let label = Label {
+        label: "new route".into(),
+        note: String::new(),
+    };
+    crate::labels::set_label(&recent, "3@2024-01-01T00:00:00+00:00", label.clone())?;
+
+    let bundle = dir.path().join("route.atlasrec");
+    export_bundle(&bundle, &[(&recent, 3)])?;
+
+    let archive = Archive::new(dir.path().join("archive"))?;
+    assert_eq!(import_bundle(&bundle, &archive)?, [0]);
+    assert_eq!(import_bundle(&bundle, &archive)?, [0]);
+
+    let archived = archive.physics_inspector();
+    assert_eq!(
+        std::fs::read_to_string(
+            archived
+                .recent_recordings
+                .join(format!("0{POSITION_LOG_SUFFIX}"))
+        )?,
+        "log"
+    );
+    let key = recording_key(&archived.room_layout(0)?);
+    assert_eq!(LabelIndex::load(archived)?.get(&key), Some(&label));
+    Ok(())
+}
@@ -0,0 +1,67 @@
+//! Colors of the annotated paths, shared by the renderers so that their output looks the same.
+//! The tables match the ones `annotate_celeste_map` draws PNGs with, which it doesn't export.
+
+/// Stops of [`ColorMode::Gradient`], reflected from the top left to the bottom right corner.
+///
+/// [`ColorMode::Gradient`]: annotate_celeste_map::ColorMode::Gradient
+pub const GRADIENT: [(f32, [u8; 3]); 3] = [
+    (0.0, [255, 0, 0]),
+    (0.5, [128, 0, 128]),
+    (1.0, [15, 30, 150]),
+];
+
+/// Colors cycled through by [`ColorMode::Random`], drawn with [`RANDOM_ALPHA`].
+///
+/// [`ColorMode::Random`]: annotate_celeste_map::ColorMode::Random
+pub const RANDOM_COLORS: [[u8; 3]; 10] = [
+    [255, 0, 0],
+    [0, 255, 0],
+    [0, 0, 255],
+    [255, 255, 0],
+    [255, 0, 255],
+    [0, 255, 255],
+    [128, 0, 128],
+    [255, 165, 0],
+    [0, 128, 0],
+    [255, 192, 203],
+];
+pub const RANDOM_ALPHA: u8 = 200;
+
+/// Color of the `n`th recording with [`ColorMode::Random`].
+///
+/// [`ColorMode::Random`]: annotate_celeste_map::ColorMode::Random
+pub fn random_color(n: usize) -> [u8; 3] {
+    RANDOM_COLORS[n % RANDOM_COLORS.len()]
+}
+
+/// Color of a player state like `StDash`, used by [`ColorMode::State`].
+///
+/// [`ColorMode::State`]: annotate_celeste_map::ColorMode::State
+pub fn state_color(state: &str) -> [u8; 3] {
+    match state {
+        "StNormal" => [0, 255, 0],
+        "StDash" => [255, 0, 0],
+        "StClimb" => [255, 255, 0],
+        "StDummy" => [255, 255, 255],
+        _ => [255, 0, 255],
+    }
+}
+
+/// Colors of the compared recordings in order, picked to be distinguishable from each other
+pub const COMPARISON_COLORS: [[u8; 3]; 10] = [
+    [230, 25, 75],
+    [60, 180, 75],
+    [0, 130, 200],
+    [245, 130, 48],
+    [145, 30, 180],
+    [70, 240, 240],
+    [240, 50, 230],
+    [210, 245, 60],
+    [250, 190, 212],
+    [0, 128, 128],
+];
+
+/// Color of the `n`th compared recording.
+pub fn comparison_color(n: usize) -> [u8; 3] {
+    COMPARISON_COLORS[n % COMPARISON_COLORS.len()]
+}
@@ -0,0 +1,392 @@
+//! Timesave comparisons, which split recordings of the same map into the rooms they went through
+//! and compare the time spent in each room against a baseline recording.
+
+use std::fmt::Write;
+
+use anyhow::{ensure, Result};
+use celesteloader::cct_physics_inspector::PhysicsInspector;
+use serde::Serialize;
+
+use crate::splits::{frames_to_finaltime, room_splits, RoomSplit, SplitTable};
+
+/// A recording to compare.
+#[derive(Clone)]
+pub struct TimesaveRecording {
+    pub physics_inspector: PhysicsInspector,
+    pub index: u32,
+    /// Shown in the comparison, like `#3` or a label
+    pub name: String,
+}
+
+/// The recordings of one map to compare with each other.
+#[derive(Clone)]
+pub struct TimesaveSelection {
+    pub map_bin: String,
+    /// Shown in the comparison, see [`crate::names::MapNames`]
+    pub map_name: String,
+    pub recordings: Vec<TimesaveRecording>,
+    /// Position of the recording in `recordings` the others are compared to, the slowest one if unset
+    pub baseline: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordingSplits {
+    pub name: String,
+    pub frames: u32,
+    pub rooms: Vec<RoomSplit>,
+}
+
+impl RecordingSplits {
+    fn room(&self, room: &RoomSplit) -> Option<&RoomSplit> {
+        self.rooms
+            .iter()
+            .find(|other| other.room == room.room && other.visit == room.visit)
+    }
+}
+
+/// Recordings of a map compared against a baseline.
+#[derive(Clone, Debug)]
+pub struct MapTimesave {
+    pub map_bin: String,
+    pub map_name: String,
+    pub baseline: RecordingSplits,
+    pub others: Vec<RecordingSplits>,
+}
+
+/// Frames `other` spent in a room compared to the baseline, negative if it was faster.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RoomDelta<'a> {
+    Both {
+        room: &'a RoomSplit,
+        delta: i64,
+    },
+    /// Only `other` went through the room
+    Added(&'a RoomSplit),
+    /// Only the baseline went through the room
+    Skipped(&'a RoomSplit),
+}
+
+impl MapTimesave {
+    /// Per-room deltas of `other`, in the order of its rooms followed by the rooms it skipped.
+    pub fn room_deltas<'a>(&'a self, other: &'a RecordingSplits) -> Vec<RoomDelta<'a>> {
+        let mut deltas: Vec<_> = other
+            .rooms
+            .iter()
+            .map(|room| match self.baseline.room(room) {
+                Some(baseline) => RoomDelta::Both {
+                    room,
+                    delta: room.frames as i64 - baseline.frames as i64,
+                },
+                None => RoomDelta::Added(room),
+            })
+            .collect();
+        deltas.extend(
+            self.baseline
+                .rooms
+                .iter()
+                .filter(|room| other.room(room).is_none())
+                .map(RoomDelta::Skipped),
+        );
+        deltas
+    }
+
+    /// The total and every room of each recording compared to the baseline.
+    pub fn rows(&self) -> Vec<TimesaveRow> {
+        let row = |other: &RecordingSplits, room: String, frames_baseline, frames| TimesaveRow {
+            map: self.map_name.clone(),
+            baseline: self.baseline.name.clone(),
+            recording: other.name.clone(),
+            room,
+            frames_baseline,
+            frames,
+            delta: frames_baseline
+                .zip(frames)
+                .map(|(baseline, frames)| frames as i64 - baseline as i64),
+        };
+
+        let mut rows = Vec::new();
+        for other in &self.others {
+            rows.push(row(
+                other,
+                TOTAL.into(),
+                Some(self.baseline.frames),
+                Some(other.frames),
+            ));
+            for delta in self.room_deltas(other) {
+                rows.push(match delta {
+                    RoomDelta::Both { room, .. } => row(
+                        other,
+                        room.name(),
+                        self.baseline.room(room).map(|baseline| baseline.frames),
+                        Some(room.frames),
+                    ),
+                    RoomDelta::Added(room) => row(other, room.name(), None, Some(room.frames)),
+                    RoomDelta::Skipped(room) => row(other, room.name(), Some(room.frames), None),
+                });
+            }
+        }
+        rows
+    }
+
+    /// Text summary with the total and every room that differs for each recording.
+    pub fn render(&self) -> String {
+        let mut s = String::new();
+        let _ = writeln!(
+            s,
+            "{}: {} {}",
+            self.map_name,
+            self.baseline.name,
+            frames_to_finaltime(self.baseline.frames)
+        );
+
+        for other in &self.others {
+            let _ = writeln!(
+                s,
+                "{} {}: {}",
+                signed(other.frames as i64 - self.baseline.frames as i64),
+                other.name,
+                frames_to_finaltime(other.frames)
+            );
+            for delta in self.room_deltas(other) {
+                let _ = match delta {
+                    RoomDelta::Both { delta: 0, .. } => Ok(()),
+                    RoomDelta::Both { room, delta } => {
+                        writeln!(s, "  {} [{}]", signed(delta), room.name())
+                    }
+                    RoomDelta::Added(room) => {
+                        writeln!(
+                            s,
+                            "  {} [{}] not in baseline",
+                            signed(room.frames as i64),
+                            room.name()
+                        )
+                    }
+                    RoomDelta::Skipped(room) => {
+                        writeln!(
+                            s,
+                            "  {} [{}] skipped",
+                            signed(-(room.frames as i64)),
+                            room.name()
+                        )
+                    }
+                };
+            }
+        }
+
+        s
+    }
+}
+
+/// Room of [`TimesaveRow`]s comparing whole recordings
+pub const TOTAL: &str = "Total";
+
+/// A room of a recording compared to the same room of the baseline.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct TimesaveRow {
+    pub map: String,
+    pub baseline: String,
+    pub recording: String,
+    /// Name of the room, or [`TOTAL`]
+    pub room: String,
+    /// Unset if the baseline didn't go through the room
+    pub frames_baseline: Option<u32>,
+    /// Unset if the recording didn't go through the room
+    pub frames: Option<u32>,
+    pub delta: Option<i64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimesaveFormat {
+    Text,
+    /// A table per map with the rooms that differ, for posting in Discord
+    Markdown,
+    Csv,
+    Json,
+}
+
+impl TimesaveFormat {
+    pub fn from_name(name: &str) -> Option<TimesaveFormat> {
+        Some(match name.to_lowercase().as_str() {
+            "text" | "txt" => TimesaveFormat::Text,
+            "markdown" | "md" => TimesaveFormat::Markdown,
+            "csv" => TimesaveFormat::Csv,
+            "json" => TimesaveFormat::Json,
+            _ => return None,
+        })
+    }
+}
+
+/// Formats timesave comparisons. CSV and JSON contain every [`TimesaveRow`], the others only
+/// the rooms that differ.
+pub fn export_timesave(comparisons: &[MapTimesave], format: TimesaveFormat) -> Result<String> {
+    let cell = |value: Option<u32>| value.map(|value| value.to_string()).unwrap_or_default();
+    let delta = |value: Option<i64>| value.map(|value| format!("{value:+}")).unwrap_or_default();
+
+    Ok(match format {
+        TimesaveFormat::Text => comparisons
+            .iter()
+            .map(MapTimesave::render)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        TimesaveFormat::Markdown => comparisons
+            .iter()
+            .map(|comparison| {
+                let table = SplitTable {
+                    headers: ["Recording", "Room", "Baseline", "Frames", "Delta"]
+                        .map(String::from)
+                        .into(),
+                    rows: comparison
+                        .rows()
+                        .into_iter()
+                        .filter(|row| row.room == TOTAL || row.delta != Some(0))
+                        .map(|row| {
+                            vec![
+                                row.recording,
+                                row.room,
+                                cell(row.frames_baseline),
+                                cell(row.frames),
+                                delta(row.delta),
+                            ]
+                        })
+                        .collect(),
+                };
+                format!(
+                    "**{}** compared to {}\n\n{}",
+                    comparison.map_name,
+                    comparison.baseline.name,
+                    table.to_markdown()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        TimesaveFormat::Csv => SplitTable {
+            headers: [
+                "Map",
+                "Baseline",
+                "Recording",
+                "Room",
+                "Frames baseline",
+                "Frames",
+                "Delta",
+            ]
+            .map(String::from)
+            .into(),
+            rows: comparisons
+                .iter()
+                .flat_map(MapTimesave::rows)
+                .map(|row| {
+                    vec![
+                        row.map,
+                        row.baseline,
+                        row.recording,
+                        row.room,
+                        cell(row.frames_baseline),
+                        cell(row.frames),
+                        delta(row.delta),
+                    ]
+                })
+                .collect(),
+        }
+        .to_csv(),
+        TimesaveFormat::Json => {
+            let rows: Vec<_> = comparisons.iter().flat_map(MapTimesave::rows).collect();
+            serde_json::to_string_pretty(&rows)?
+        }
+    })
+}
+
+/// Compares the recordings of each map against their baseline.
+pub fn compare_timesave(maps: &[TimesaveSelection]) -> Result<Vec<MapTimesave>> {
+    let mut comparisons = Vec::new();
+
+    for selection in maps {
+        let map_bin = &selection.map_bin;
+        ensure!(
+            selection.recordings.len() >= 2,
+            "check at least two recordings of {map_bin} to compare them"
+        );
+
+        let mut splits = selection
+            .recordings
+            .iter()
+            .map(|recording| {
+                let rooms = room_splits(&recording.physics_inspector, recording.index)?;
+                Ok(RecordingSplits {
+                    name: recording.name.clone(),
+                    frames: rooms.iter().map(|room| room.frames).sum(),
+                    rooms,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let baseline = selection.baseline.unwrap_or_else(|| {
+            (0..splits.len())
+                .max_by_key(|&i| splits[i].frames)
+                .unwrap_or_default()
+        });
+        ensure!(
+            baseline < splits.len(),
+            "baseline {baseline} is not one of the {} recordings of {map_bin}",
+            splits.len()
+        );
+        let baseline = splits.remove(baseline);
+
+        comparisons.push(MapTimesave {
+            map_bin: map_bin.clone(),
+            map_name: selection.map_name.clone(),
+            baseline,
+            others: splits,
+        });
+    }
+
+    Ok(comparisons)
+}
+
+fn signed(frames: i64) -> String {
+    format!("{frames:+}f")
+}
+
+#[test]
+fn room_deltas() {
+    let split = |room, visit, frames| RoomSplit::new(room, visit, 0, frames);
+    let splits = |rooms: Vec<RoomSplit>| RecordingSplits {
+        name: String::new(),
+        frames: rooms.iter().map(|room| room.frames).sum(),
+        rooms,
+    };
+
+    let comparison = MapTimesave {
+        map_bin: String::new(),
+        map_name: String::new(),
+        baseline: splits(vec![
+            split("a", 0, 100),
+            split("b", 0, 50),
+            split("a", 1, 30),
+        ]),
+        others: vec![splits(vec![split("a", 0, 90), split("c", 0, 20)])],
+    };
+    assert_eq!(
+        comparison.room_deltas(&comparison.others[0]),
+        [
+            RoomDelta::Both {
+                room: &split("a", 0, 90),
+                delta: -10
+            },
+            RoomDelta::Added(&split("c", 0, 20)),
+            RoomDelta::Skipped(&split("b", 0, 50)),
+            RoomDelta::Skipped(&split("a", 1, 30)),
+        ]
+    );
+
+    let rows = comparison.rows();
+    assert_eq!(rows.len(), 5);
+    assert_eq!((rows[0].room.as_str(), rows[0].delta), (TOTAL, Some(-70)));
+    assert_eq!((rows[2].frames_baseline, rows[2].frames), (None, Some(20)));
+
+    let comparisons = std::slice::from_ref(&comparison);
+    let csv = export_timesave(comparisons, TimesaveFormat::Csv).unwrap();
+    assert_eq!(csv.lines().count(), 6);
+    let json: serde_json::Value =
+        serde_json::from_str(&export_timesave(comparisons, TimesaveFormat::Json).unwrap()).unwrap();
+    assert_eq!(json[1]["delta"], -10);
+}
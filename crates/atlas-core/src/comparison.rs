@@ -0,0 +1,274 @@
+//! Comparison renders, where every recording gets its own color, a legend tells them apart
+//! and markers show where the paths diverge.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use ab_glyph::{Font, FontVec, OutlineCurve, PxScale, PxScaleFont, ScaleFont};
+use annotate_celeste_map::{ColorMode, LineSettings};
+use anyhow::{Context, Result};
+use celesteloader::{cct_physics_inspector::PhysicsInspector, map::Bounds};
+use chrono::DateTime;
+use tiny_skia::{Color, FillRule, Paint, PathBuilder, Pixmap, Rect, Stroke, Transform};
+
+use crate::colors::comparison_color;
+
+const FONT_SIZE: f32 = 20.0;
+
+/// Draws each recording in its own color, marks where the others diverge from the first one
+/// and puts a legend into the top left corner.
+pub fn annotate_comparison(
+    image: &mut Pixmap,
+    physics_inspector: &PhysicsInspector,
+    recordings: &[u32],
+    labels: &HashMap<u32, String>,
+    bounds: Bounds,
+    line_settings: LineSettings,
+) -> Result<()> {
+    for (n, &recording) in recordings.iter().enumerate() {
+        let [r, g, b] = comparison_color(n);
+        annotate_celeste_map::annotate_cct_recording_skia(
+            image,
+            physics_inspector,
+            std::iter::once(recording),
+            bounds,
+            LineSettings {
+                color_mode: ColorMode::Color([r, g, b, 255]),
+                ..line_settings
+            },
+        )?;
+    }
+
+    let positions = recordings
+        .iter()
+        .map(|&recording| positions(physics_inspector, recording))
+        .collect::<Result<Vec<_>>>()?;
+    if let Some((reference, others)) = positions.split_first() {
+        for (n, other) in others.iter().enumerate() {
+            if let Some((x, y)) = divergence_point(reference, other) {
+                draw_marker(
+                    image,
+                    x - bounds.position.x as f32,
+                    y - bounds.position.y as f32,
+                    comparison_color(n + 1),
+                    line_settings.width,
+                );
+            }
+        }
+    }
+
+    let mut rows = Vec::new();
+    for (n, &recording) in recordings.iter().enumerate() {
+        let layout = physics_inspector.room_layout(recording)?;
+        let started = DateTime::parse_from_rfc3339(&layout.recording_started).map_or_else(
+            |_| "?".to_owned(),
+            |date| date.format("%d.%m.%Y %R").to_string(),
+        );
+
+        let mut text = format!(
+            "#{recording}   {started}   {}f ({:.3}s)",
+            layout.frame_count,
+            layout.frame_count as f32 / 60.0
+        );
+        if let Some(label) = labels.get(&recording) {
+            text.push_str("   ");
+            text.push_str(label);
+        }
+        rows.push((comparison_color(n), text));
+    }
+    draw_legend(image, &rows)
+}
+
+fn positions(physics_inspector: &PhysicsInspector, recording: u32) -> Result<Vec<(f32, f32)>> {
+    physics_inspector
+        .position_log(recording)?
+        .map(|item| item.map(|item| (item.x, item.y)))
+        .collect()
+}
+
+/// The last position both recordings shared, or the start of `other` if they started apart.
+/// `None` if one is a prefix of the other.
+fn divergence_point(reference: &[(f32, f32)], other: &[(f32, f32)]) -> Option<(f32, f32)> {
+    let first_difference = reference.iter().zip(other).position(|(a, b)| a != b)?;
+    match first_difference {
+        0 => other.first().copied(),
+        i => Some(other[i - 1]),
+    }
+}
+
+fn draw_marker(image: &mut Pixmap, x: f32, y: f32, [r, g, b]: [u8; 3], line_width: f32) {
+    let radius = (line_width * 3.0).max(6.0);
+    let Some(circle) = PathBuilder::from_circle(x, y, radius) else {
+        return;
+    };
+
+    let mut paint = Paint {
+        anti_alias: true,
+        ..Default::default()
+    };
+    paint.set_color_rgba8(r, g, b, 255);
+    image.fill_path(
+        &circle,
+        &paint,
+        FillRule::Winding,
+        Transform::identity(),
+        None,
+    );
+
+    paint.set_color(Color::WHITE);
+    let stroke = Stroke {
+        width: (line_width / 2.0).max(2.0),
+        ..Default::default()
+    };
+    image.stroke_path(&circle, &paint, &stroke, Transform::identity(), None);
+}
+
+/// Sans-serif system font for the legend, looked up once instead of bundling one.
+fn legend_font() -> Result<&'static FontVec> {
+    static FONT: OnceLock<Option<FontVec>> = OnceLock::new();
+    FONT.get_or_init(|| {
+        let mut db = fontdb::Database::new();
+        db.load_system_fonts();
+        let families = [
+            fontdb::Family::SansSerif,
+            fontdb::Family::Name("DejaVu Sans"),
+            fontdb::Family::Name("Noto Sans"),
+            fontdb::Family::Name("Liberation Sans"),
+        ];
+        let id = db
+            .query(&fontdb::Query {
+                families: &families,
+                ..Default::default()
+            })
+            .or_else(|| Some(db.faces().next()?.id))?;
+        db.with_face_data(id, |data, index| {
+            FontVec::try_from_vec_and_index(data.to_vec(), index).ok()
+        })?
+    })
+    .as_ref()
+    .context("no system font found to draw the legend with")
+}
+
+fn draw_legend(image: &mut Pixmap, rows: &[([u8; 3], String)]) -> Result<()> {
+    let font = legend_font()?.as_scaled(PxScale::from(FONT_SIZE));
+
+    let padding = 10.0;
+    let row_height = FONT_SIZE * 1.4;
+    let swatch_size = FONT_SIZE * 0.8;
+
+    let text_width = rows
+        .iter()
+        .map(|(_, text)| text_width(&font, text))
+        .fold(0.0, f32::max);
+    let width = padding * 3.0 + swatch_size + text_width;
+    let height = padding * 2.0 + row_height * rows.len() as f32;
+
+    let mut paint = Paint::default();
+    if let Some(rect) = Rect::from_xywh(padding, padding, width, height) {
+        paint.set_color_rgba8(0, 0, 0, 200);
+        image.fill_rect(rect, &paint, Transform::identity(), None);
+    }
+
+    paint.anti_alias = true;
+    for (i, ([r, g, b], text)) in rows.iter().enumerate() {
+        let top = padding * 2.0 + row_height * i as f32;
+
+        if let Some(swatch) = Rect::from_xywh(
+            padding * 2.0,
+            top + (row_height - swatch_size) / 2.0,
+            swatch_size,
+            swatch_size,
+        ) {
+            paint.set_color_rgba8(*r, *g, *b, 255);
+            image.fill_rect(swatch, &paint, Transform::identity(), None);
+        }
+
+        let baseline = top + (row_height + font.ascent() + font.descent()) / 2.0;
+        if let Some(path) = text_path(&font, text, padding * 3.0 + swatch_size, baseline) {
+            paint.set_color(Color::WHITE);
+            image.fill_path(
+                &path,
+                &paint,
+                FillRule::Winding,
+                Transform::identity(),
+                None,
+            );
+        }
+    }
+    Ok(())
+}
+
+fn text_width(font: &PxScaleFont<&FontVec>, text: &str) -> f32 {
+    text.chars().map(|c| font.h_advance(font.glyph_id(c))).sum()
+}
+
+fn text_path(
+    font: &PxScaleFont<&FontVec>,
+    text: &str,
+    x: f32,
+    baseline: f32,
+) -> Option<tiny_skia::Path> {
+    let scale = font.scale_factor();
+    let mut pb = PathBuilder::new();
+
+    let mut caret = x;
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(outline) = font.font().outline(id) {
+            let point = |p: ab_glyph::Point| {
+                (
+                    caret + p.x * scale.horizontal,
+                    baseline - p.y * scale.vertical,
+                )
+            };
+
+            let mut last = None;
+            for curve in outline.curves {
+                let (start, end) = match curve {
+                    OutlineCurve::Line(a, b) => (a, b),
+                    OutlineCurve::Quad(a, _, c) => (a, c),
+                    OutlineCurve::Cubic(a, _, _, d) => (a, d),
+                };
+                if last != Some(start) {
+                    if last.is_some() {
+                        pb.close();
+                    }
+                    let (x, y) = point(start);
+                    pb.move_to(x, y);
+                }
+                match curve {
+                    OutlineCurve::Line(_, b) => {
+                        let (x, y) = point(b);
+                        pb.line_to(x, y);
+                    }
+                    OutlineCurve::Quad(_, b, c) => {
+                        let ((x1, y1), (x, y)) = (point(b), point(c));
+                        pb.quad_to(x1, y1, x, y);
+                    }
+                    OutlineCurve::Cubic(_, b, c, d) => {
+                        let ((x1, y1), (x2, y2), (x, y)) = (point(b), point(c), point(d));
+                        pb.cubic_to(x1, y1, x2, y2, x, y);
+                    }
+                }
+                last = Some(end);
+            }
+            if last.is_some() {
+                pb.close();
+            }
+        }
+        caret += font.h_advance(id);
+    }
+
+    pb.finish()
+}
+
+#[test]
+fn divergence() {
+    let a = [(0.0, 0.0), (1.0, 0.0), (2.0, 0.0)];
+    let b = [(0.0, 0.0), (1.0, 0.0), (2.0, 1.0)];
+    let c = [(5.0, 5.0), (1.0, 0.0)];
+
+    assert_eq!(divergence_point(&a, &b), Some((1.0, 0.0)));
+    assert_eq!(divergence_point(&a, &c), Some((5.0, 5.0)));
+    assert_eq!(divergence_point(&a, &a[..2]), None);
+}
@@ -0,0 +1,147 @@
+//! Finding Celeste installations on the system.
+
+#[cfg(target_os = "linux")]
+use std::path::{Path, PathBuf};
+
+use celesteloader::CelesteInstallation;
+
+/// Returns all valid Celeste installations that could be found, without duplicates.
+///
+/// On Linux this looks through all Steam library folders (including Flatpak and Snap Steam),
+/// the itch app and installations managed by Olympus.
+pub fn detect_installations() -> Vec<CelesteInstallation> {
+    let mut candidates = Vec::new();
+
+    #[cfg(target_os = "linux")]
+    candidates.extend(linux_candidates());
+
+    if let Ok(installations) = CelesteInstallation::detect_multiple() {
+        candidates.extend(installations.into_iter().map(|celeste| celeste.path));
+    }
+
+    let mut installations: Vec<CelesteInstallation> = Vec::new();
+    for candidate in candidates {
+        let Ok(celeste) = crate::celeste_installation_from_path(candidate) else {
+            continue;
+        };
+        let canonical = celeste.path.canonicalize().ok();
+        let duplicate = installations
+            .iter()
+            .any(|other| other.path.canonicalize().ok() == canonical);
+        if !duplicate {
+            installations.push(celeste);
+        }
+    }
+
+    installations
+}
+
+#[cfg(target_os = "linux")]
+fn linux_candidates() -> Vec<PathBuf> {
+    let Some(home) = dirs::home_dir() else {
+        return Vec::new();
+    };
+    let snap_dir = std::env::var_os("SNAP_USER_DATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join("snap"));
+
+    let steam_roots = [
+        home.join(".local/share/Steam"),
+        home.join(".steam/steam"),
+        home.join(".steam/root"),
+        home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"),
+        home.join(".var/app/com.valvesoftware.Steam/data/Steam"),
+        home.join(".var/app/com.valvesoftware.Steam/.steam/steam"),
+        snap_dir.join("steam/common/.local/share/Steam"),
+    ];
+
+    let mut candidates = Vec::new();
+    for root in steam_roots.iter().filter(|root| root.is_dir()) {
+        let mut libraries = vec![root.clone()];
+        for vdf in ["steamapps/libraryfolders.vdf", "config/libraryfolders.vdf"] {
+            if let Ok(contents) = std::fs::read_to_string(root.join(vdf)) {
+                libraries.extend(parse_library_folders(&contents));
+            }
+        }
+
+        candidates.extend(
+            libraries
+                .into_iter()
+                .map(|library| library.join("steamapps/common/Celeste")),
+        );
+    }
+
+    if let Some(config_dir) = dirs::config_dir() {
+        candidates.push(config_dir.join("itch/apps/celeste"));
+        candidates.push(config_dir.join("itch/apps/Celeste"));
+        candidates.extend(olympus_installs(&config_dir.join("Olympus/config.json")));
+    }
+    if let Some(data_dir) = dirs::data_dir() {
+        candidates.extend(olympus_installs(&data_dir.join("love/Olympus/config.json")));
+    }
+
+    candidates
+}
+
+/// Parses the library paths out of Steam's `libraryfolders.vdf`.
+#[cfg(target_os = "linux")]
+fn parse_library_folders(vdf: &str) -> Vec<PathBuf> {
+    vdf.lines()
+        .filter_map(|line| {
+            let mut tokens = line.split('"').filter(|token| !token.trim().is_empty());
+            let key = tokens.next()?;
+            let value = tokens.next()?;
+            key.eq_ignore_ascii_case("path")
+                .then(|| PathBuf::from(value.replace("\\\\", "\\")))
+        })
+        .collect()
+}
+
+/// Reads the installations added to Olympus, the Everest installer.
+#[cfg(target_os = "linux")]
+fn olympus_installs(config: &Path) -> Vec<PathBuf> {
+    let Ok(contents) = std::fs::read_to_string(config) else {
+        return Vec::new();
+    };
+    let Ok(config) = serde_json::from_str::<serde_json::Value>(&contents) else {
+        return Vec::new();
+    };
+
+    config["installs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|install| install["path"].as_str())
+        .map(PathBuf::from)
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn library_folders() {
+    let vdf = r#"
+"libraryfolders"
+{
+	"0"
+	{
+		"path"		"/home/user/.local/share/Steam"
+		"label"		""
+		"apps"
+		{
+			"504230"		"1219462784"
+		}
+	}
+	"1"
+	{
+		"path"		"/mnt/games/SteamLibrary"
+	}
+}
+"#;
+    assert_eq!(
+        parse_library_folders(vdf),
+        [
+            PathBuf::from("/home/user/.local/share/Steam"),
+            PathBuf::from("/mnt/games/SteamLibrary")
+        ]
+    );
+}
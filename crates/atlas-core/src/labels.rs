@@ -0,0 +1,70 @@
+//! User labels and notes on recordings, stored next to CCT's recordings.
+//!
+//! Like the [`ProvenanceIndex`](crate::provenance::ProvenanceIndex), they are stored in a
+//! [`Sidecar`] so they stay attached when CCT shifts its indices.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use celesteloader::cct_physics_inspector::PhysicsInspector;
+use serde::{Deserialize, Serialize};
+
+use crate::sidecar::{recording_key, Sidecar, SidecarEntry};
+
+pub const LABELS_FILE: &str = "atlas-labels.json";
+
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Label {
+    /// Short name shown in the recordings list, legends and file names
+    pub label: String,
+    pub note: String,
+}
+
+impl Label {
+    pub fn is_empty(&self) -> bool {
+        self.label.is_empty() && self.note.is_empty()
+    }
+}
+
+impl SidecarEntry for Label {
+    const FILE_NAME: &'static str = LABELS_FILE;
+}
+
+pub type LabelIndex = Sidecar<Label>;
+
+impl LabelIndex {
+    /// Sets the label of a recording, removing it if it is empty.
+    pub fn set(&mut self, key: String, label: Label) {
+        match label.is_empty() {
+            true => self.remove(&key),
+            false => self.insert(key, label),
+        }
+    }
+}
+
+/// Sets the label and note of the recording with the given [`recording_key`].
+pub fn set_label(physics_inspector: &PhysicsInspector, key: &str, label: Label) -> Result<()> {
+    let existing_keys = physics_inspector
+        .recent_recordings()?
+        .iter()
+        .map(|(_, layout)| recording_key(layout))
+        .collect();
+
+    let mut index = LabelIndex::load(physics_inspector)?;
+    index.set(key.to_owned(), label);
+    index.save(physics_inspector, &existing_keys)
+}
+
+/// The non-empty labels of the recent recordings by their current CCT index.
+pub fn labels_by_index(physics_inspector: &PhysicsInspector) -> Result<HashMap<u32, String>> {
+    let index = LabelIndex::load(physics_inspector)?;
+    Ok(physics_inspector
+        .recent_recordings()?
+        .iter()
+        .filter_map(|(i, layout)| {
+            let label = index.get(&recording_key(layout))?;
+            (!label.label.is_empty()).then(|| (*i, label.label.clone()))
+        })
+        .collect())
+}
@@ -0,0 +1,32 @@
+//! Recording, grouping and rendering of CCT physics recordings, independent of the Atlas UI.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use celesteloader::CelesteInstallation;
+
+pub mod animation;
+pub mod archive;
+pub mod bundle;
+pub mod colors;
+pub mod compare;
+pub mod comparison;
+pub mod detect;
+pub mod labels;
+pub mod names;
+pub mod provenance;
+pub mod record;
+pub mod recordings;
+pub mod render;
+pub mod sidecar;
+pub mod splits;
+pub mod svg;
+#[cfg(test)]
+mod test_utils;
+
+pub fn celeste_installation_from_path(path: PathBuf) -> Result<CelesteInstallation> {
+    if !path.join("Celeste").exists() && !path.join("Celeste.exe").exists() {
+        bail!("Directory does not contain Celeste.exe or Celeste file");
+    }
+    Ok(CelesteInstallation { path })
+}
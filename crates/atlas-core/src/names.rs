@@ -0,0 +1,198 @@
+//! Map and chapter names in the language picked by the user, read from the dialog files of
+//! Celeste and of the mod a map is from. Names missing in that language fall back to English.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use celesteloader::dialog::Dialog;
+use celesteloader::map::utils::parse_map_name;
+use celesteloader::CelesteInstallation;
+
+pub const DEFAULT_LANGUAGE: &str = "English";
+
+/// Languages of Celeste's dialog files, like `English` or `French`.
+pub fn languages(celeste: &CelesteInstallation) -> Result<Vec<String>> {
+    let mut languages = Vec::new();
+    for entry in std::fs::read_dir(celeste.path.join("Content/Dialog"))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "txt") {
+            if let Some(language) = path.file_stem().and_then(|stem| stem.to_str()) {
+                languages.push(language.to_owned());
+            }
+        }
+    }
+    languages.sort();
+    Ok(languages)
+}
+
+/// Looks up map names, remembering the ones already found.
+pub struct MapNames {
+    celeste: CelesteInstallation,
+    language: String,
+    /// Celeste's dialog in `language` and English, read when first needed
+    vanilla: Option<Vec<Dialog>>,
+    /// Names of mod maps by map bin, `None` if the mod doesn't name the map
+    mod_maps: HashMap<String, Option<String>>,
+}
+
+impl MapNames {
+    pub fn new(celeste: CelesteInstallation, language: &str) -> MapNames {
+        MapNames {
+            celeste,
+            language: language.to_owned(),
+            vanilla: None,
+            mod_maps: HashMap::new(),
+        }
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    fn languages(&self) -> Vec<String> {
+        let mut languages = vec![self.language.clone()];
+        if self.language != DEFAULT_LANGUAGE {
+            languages.push(DEFAULT_LANGUAGE.to_owned());
+        }
+        languages
+    }
+
+    /// Finds the mods of the given maps in a single pass over the mod zips. If reading the mods
+    /// failed, nothing is remembered so that the maps are looked up again.
+    pub fn resolve<'a>(&mut self, map_bins: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        let missing: HashSet<&str> = map_bins
+            .into_iter()
+            .filter(|map_bin| {
+                !map_bin.is_empty()
+                    && !map_bin.starts_with("Celeste/")
+                    && !self.mod_maps.contains_key(*map_bin)
+            })
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let languages = self.languages();
+        let found = self.celeste.mods_with(|_, mut archive| {
+            let maps: Vec<String> = archive
+                .list_files()
+                .filter_map(|file| file.strip_prefix("Maps/")?.strip_suffix(".bin"))
+                .filter(|map_bin| missing.contains(map_bin))
+                .map(str::to_owned)
+                .collect();
+            if maps.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let dialogs: Vec<Dialog> = languages
+                .iter()
+                .filter_map(|language| archive.try_get_dialog(language).ok().flatten())
+                .collect();
+            Ok(maps
+                .into_iter()
+                .map(|map_bin| {
+                    let name = mod_map_keys(&map_bin)
+                        .into_iter()
+                        .find_map(|key| dialogs.iter().find_map(|dialog| dialog.get(key)))
+                        .map(str::to_owned);
+                    (map_bin, name)
+                })
+                .collect())
+        })?;
+
+        // maps not found in any mod are remembered too, so they aren't searched for again
+        let mut found: HashMap<String, Option<String>> = found.into_iter().flatten().collect();
+        for map_bin in missing {
+            let name = found.remove(map_bin).flatten();
+            self.mod_maps.insert(map_bin.to_owned(), name);
+        }
+        Ok(())
+    }
+
+    /// Name of the map, `None` if neither Celeste nor its mod have one.
+    pub fn map_name(&mut self, map_bin: &str) -> Option<String> {
+        if let Some(sid) = map_bin.strip_prefix("Celeste/") {
+            return self.vanilla(&vanilla_area_key(sid)?);
+        }
+
+        if let Err(e) = self.resolve([map_bin]) {
+            eprintln!("Failed to look up the name of {map_bin}: {e:?}");
+        }
+        self.mod_maps.get(map_bin).cloned().flatten()
+    }
+
+    /// Name of the map followed by its side, like `Forsaken City B-Side`.
+    pub fn chapter_name(&mut self, map_bin: &str, side_name: &str) -> Option<String> {
+        let name = self.map_name(map_bin)?;
+        let side_key = match side_name {
+            "" | "A-Side" => return Some(name),
+            "B-Side" => Some("overworld_remix"),
+            "C-Side" => Some("overworld_remix2"),
+            _ => None,
+        };
+        let side_name = side_key
+            .and_then(|key| self.vanilla(key))
+            .unwrap_or_else(|| side_name.to_owned());
+        Some(format!("{name} {side_name}"))
+    }
+
+    fn vanilla(&mut self, key: &str) -> Option<String> {
+        let languages = self.languages();
+        let dialogs = self.vanilla.get_or_insert_with(|| {
+            languages
+                .iter()
+                .filter_map(|language| {
+                    let path = format!("Content/Dialog/{language}.txt");
+                    match self.celeste.read_to_string(&path) {
+                        Ok(text) => Some(Dialog::from_txt(&text)),
+                        Err(e) => {
+                            eprintln!("Failed to read {path}: {e:?}");
+                            None
+                        }
+                    }
+                })
+                .collect()
+        });
+        dialogs
+            .iter()
+            .find_map(|dialog| dialog.get(key))
+            .map(str::to_owned)
+    }
+}
+
+/// Dialog key of the name of a vanilla chapter, like `area_1` for `1H-ForsakenCity`.
+fn vanilla_area_key(sid: &str) -> Option<String> {
+    let area = match sid {
+        sid if sid.starts_with("LostLevels") => 10,
+        sid => parse_map_name(sid).order?,
+    };
+    Some(format!("area_{area}"))
+}
+
+/// Dialog keys a mod map can be named by, the B- and C-Side maps share the name of the A-Side.
+fn mod_map_keys(map_bin: &str) -> Vec<&str> {
+    let mut keys = vec![map_bin];
+    if let Some(a_side) = map_bin
+        .strip_suffix("-B")
+        .or_else(|| map_bin.strip_suffix("-C"))
+    {
+        keys.push(a_side);
+    }
+    keys
+}
+
+#[test]
+fn dialog_keys() {
+    assert_eq!(
+        vanilla_area_key("1-ForsakenCity").as_deref(),
+        Some("area_1")
+    );
+    assert_eq!(
+        vanilla_area_key("1H-ForsakenCity").as_deref(),
+        Some("area_1")
+    );
+    assert_eq!(vanilla_area_key("7X-Summit").as_deref(), Some("area_7"));
+    assert_eq!(vanilla_area_key("LostLevels").as_deref(), Some("area_10"));
+    assert_eq!(vanilla_area_key("Credits"), None);
+    assert_eq!(mod_map_keys("Mod/1-Map-B"), ["Mod/1-Map-B", "Mod/1-Map"]);
+}
@@ -0,0 +1,97 @@
+//! Sidecar index stored next to CCT's recordings, remembering which TAS file produced a recording.
+
+use std::path::PathBuf;
+
+use celesteloader::cct_physics_inspector::CCTRoomLayout;
+use serde::{Deserialize, Serialize};
+
+use crate::sidecar::{Sidecar, SidecarEntry};
+
+pub const INDEX_FILE: &str = "atlas-provenance.json";
+
+/// Which version of the TAS file was recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Variant {
+    /// The file as it is on disk
+    Full,
+    /// Only the part of the file that changed since the last git commit
+    Changes,
+    /// The file as of the last git commit
+    Original,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub tas_file: PathBuf,
+    /// Short id of the git commit the file was compared against, if it is in a repository
+    pub commit: Option<String>,
+    pub variant: Variant,
+    /// RFC 3339 timestamp of when Atlas recorded the file
+    pub recorded_at: String,
+}
+
+impl Provenance {
+    /// Short description like `1A.tas (changes since 1a2b3c4)`.
+    pub fn describe(&self) -> String {
+        let file = self.tas_file.file_name().map_or_else(
+            || self.tas_file.to_string_lossy(),
+            |name| name.to_string_lossy(),
+        );
+        match (self.variant, &self.commit) {
+            (Variant::Full, None) => file.into_owned(),
+            (Variant::Full, Some(commit)) => format!("{file} @ {commit}"),
+            (Variant::Changes, None) => format!("{file} (changes)"),
+            (Variant::Changes, Some(commit)) => format!("{file} (changes since {commit})"),
+            (Variant::Original, None) => format!("{file} (original)"),
+            (Variant::Original, Some(commit)) => format!("{file} (original @ {commit})"),
+        }
+    }
+}
+
+impl SidecarEntry for Provenance {
+    const FILE_NAME: &'static str = INDEX_FILE;
+}
+
+/// Provenance of the recordings made through Atlas.
+pub type ProvenanceIndex = Sidecar<Provenance>;
+
+/// Assigns the recordings created by one run to the files of that run, in the order they were played.
+///
+/// Returns nothing if the recordings can't be attributed unambiguously.
+pub fn attribute<'a>(
+    new_recordings: &[&'a CCTRoomLayout],
+    files: &'a [Provenance],
+) -> Vec<(&'a CCTRoomLayout, &'a Provenance)> {
+    let mut new_recordings = new_recordings.to_vec();
+    new_recordings.sort_by(|a, b| a.recording_started.cmp(&b.recording_started));
+
+    match files {
+        [file] => new_recordings
+            .into_iter()
+            .map(|layout| (layout, file))
+            .collect(),
+        files if files.len() == new_recordings.len() => {
+            new_recordings.into_iter().zip(files).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[test]
+fn describe() {
+    let provenance = Provenance {
+        tas_file: PathBuf::from("/tas/1A.tas"),
+        commit: Some("1a2b3c4".into()),
+        variant: Variant::Changes,
+        recorded_at: "2024-01-01T00:00:00+00:00".into(),
+    };
+    assert_eq!(provenance.describe(), "1A.tas (changes since 1a2b3c4)");
+
+    let provenance = Provenance {
+        commit: None,
+        variant: Variant::Full,
+        ..provenance
+    };
+    assert_eq!(provenance.describe(), "1A.tas");
+}
@@ -0,0 +1,687 @@
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{ensure, Context, Result};
+use celestedebugrc::{DebugRC, PlayTasProgress};
+use celesteloader::cct_physics_inspector::{CCTRoomLayout, PhysicsInspector};
+use celesteloader::CelesteInstallation;
+
+use crate::provenance::{self, Provenance, ProvenanceIndex, Variant};
+use crate::sidecar;
+
+/// Options for [`record_tases`].
+#[derive(Clone, Debug)]
+pub struct RecordOptions {
+    pub fastforward_speed: f32,
+    pub run_as_merged: bool,
+    pub record_git_tree: bool,
+    pub only_record_changes: bool,
+    pub enable_tas_recorder: bool,
+}
+
+/// Shared flag to abort a running [`record_tases`] from another thread.
+#[derive(Clone, Default, Debug)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Returned by [`record_tases`] when it was stopped through its [`CancellationToken`].
+#[derive(Debug)]
+pub struct Aborted;
+
+impl std::fmt::Display for Aborted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Recording was aborted")
+    }
+}
+
+impl std::error::Error for Aborted {}
+
+/// Runs the TAS files through DebugRC with physics logging enabled and blocks until they are done.
+///
+/// `on_progress` receives the overall progress in `0..=1` and a status message.
+/// Cancelling `cancel` stops the running TAS and returns [`Aborted`].
+/// Temporary `.tas` files and ghosts are cleaned up regardless of whether recording succeeded.
+/// The files that produced the new recordings are remembered in the [`ProvenanceIndex`].
+pub fn record_tases(
+    paths: &[PathBuf],
+    options: &RecordOptions,
+    celeste: &CelesteInstallation,
+    debugrc: &DebugRC,
+    cancel: &CancellationToken,
+    mut on_progress: impl FnMut(f32, String),
+) -> Result<()> {
+    let mut tmp_files = Vec::new();
+    let record_ghost = options.record_git_tree && options.enable_tas_recorder;
+
+    let physics_inspector = PhysicsInspector::new(celeste);
+    let known_recordings: HashSet<String> = physics_inspector
+        .recent_recordings()
+        .unwrap_or_default()
+        .iter()
+        .map(|(_, layout)| sidecar::recording_key(layout))
+        .collect();
+
+    let result = prepare_files(paths, options, &mut tmp_files).and_then(|files| {
+        let speed = match options.enable_tas_recorder {
+            true => 1.0,
+            false => options.fastforward_speed,
+        };
+        run_tases_fastforward(
+            debugrc,
+            &files,
+            speed,
+            options.run_as_merged,
+            cancel,
+            |status| {
+                let percentage_in_tas = status
+                    .current_frame
+                    .parse::<u32>()
+                    .ok()
+                    .and_then(|current| {
+                        let total = status.total_frames.parse::<u32>().ok()?;
+                        Some((current, total))
+                    })
+                    .map(|(current, total)| current as f32 / total as f32)
+                    .unwrap_or(1.0);
+
+                let (msg, progress) = if let Some(origin) = status.origin {
+                    let msg = format!(
+                        "{}/{} {origin}: {}/{}",
+                        status.current_file + 1,
+                        status.total_files,
+                        status.current_frame,
+                        status.total_frames
+                    );
+                    let percentage = (status.current_file as f32 + percentage_in_tas)
+                        / status.total_files as f32;
+                    (msg, percentage)
+                } else {
+                    let msg = format!("{}/{}", status.current_frame, status.total_frames);
+                    (msg, percentage_in_tas)
+                };
+
+                on_progress(progress, msg);
+            },
+        )?;
+
+        if let Err(e) = record_provenance(&physics_inspector, &known_recordings, &files) {
+            eprintln!("Failed to record provenance: {e:?}");
+        }
+        Ok(())
+    });
+
+    for file in tmp_files {
+        let _ = std::fs::remove_file(&file);
+    }
+
+    if record_ghost {
+        let save_dir = celeste.save_dir().join("GhostsForTas");
+        if save_dir.is_dir() {
+            if let Ok(dir) = save_dir.read_dir() {
+                for item in dir {
+                    if let Ok(item) = item {
+                        let _ = std::fs::remove_file(item.path());
+                    };
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// State of a single file in [`record_queue`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum FileStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed(String),
+}
+
+/// Records the files one after another, or all at once if `run_as_merged` is set,
+/// reporting the status of each file by its index in `paths`.
+///
+/// A failing file doesn't stop the queue. When aborted, the running file is reset to
+/// [`FileStatus::Pending`] and [`Aborted`] is returned.
+pub fn record_queue(
+    paths: &[PathBuf],
+    options: &RecordOptions,
+    celeste: &CelesteInstallation,
+    debugrc: &DebugRC,
+    cancel: &CancellationToken,
+    mut on_status: impl FnMut(usize, FileStatus),
+    mut on_progress: impl FnMut(f32, String),
+) -> Result<()> {
+    let batches: Vec<Vec<usize>> = match options.run_as_merged {
+        true => vec![(0..paths.len()).collect()],
+        false => (0..paths.len()).map(|i| vec![i]).collect(),
+    };
+
+    let total = paths.len() as f32;
+    let mut done = 0;
+    for batch in batches {
+        if cancel.is_cancelled() {
+            return Err(Aborted.into());
+        }
+
+        for &i in &batch {
+            on_status(i, FileStatus::Running);
+        }
+        let batch_paths: Vec<PathBuf> = batch.iter().map(|&i| paths[i].clone()).collect();
+        let result = record_tases(
+            &batch_paths,
+            options,
+            celeste,
+            debugrc,
+            cancel,
+            |progress, msg| {
+                let progress = (done as f32 + progress * batch.len() as f32) / total;
+                on_progress(progress, msg);
+            },
+        );
+
+        let status = match result {
+            Err(e) if e.is::<Aborted>() => {
+                for &i in &batch {
+                    on_status(i, FileStatus::Pending);
+                }
+                return Err(e);
+            }
+            Err(e) => FileStatus::Failed(format!("{e:#}")),
+            Ok(()) => FileStatus::Succeeded,
+        };
+        for &i in &batch {
+            on_status(i, status.clone());
+        }
+        done += batch.len();
+    }
+
+    Ok(())
+}
+
+/// Remembers which of `files` produced the recordings that aren't in `known_recordings`.
+fn record_provenance(
+    physics_inspector: &PhysicsInspector,
+    known_recordings: &HashSet<String>,
+    files: &[PreparedFile],
+) -> Result<()> {
+    let is_new = |layout: &CCTRoomLayout| {
+        layout.frame_count > 1 && !known_recordings.contains(&sidecar::recording_key(layout))
+    };
+
+    // CCT writes the recording shortly after physics logging is disabled
+    let mut recordings = Vec::new();
+    for _ in 0..10 {
+        recordings = physics_inspector.recent_recordings()?;
+        let new_count = recordings
+            .iter()
+            .filter(|(_, layout)| is_new(layout))
+            .count();
+        if new_count >= files.len() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    let new_recordings: Vec<&CCTRoomLayout> = recordings
+        .iter()
+        .map(|(_, layout)| layout)
+        .filter(|layout| is_new(layout))
+        .collect();
+    let provenance: Vec<Provenance> = files.iter().map(|file| file.provenance.clone()).collect();
+
+    let mut index = ProvenanceIndex::load(physics_inspector)?;
+    for (layout, provenance) in provenance::attribute(&new_recordings, &provenance) {
+        index.insert(sidecar::recording_key(layout), provenance.clone());
+    }
+
+    let existing = recordings
+        .iter()
+        .map(|(_, layout)| sidecar::recording_key(layout))
+        .collect();
+    index.save(physics_inspector, &existing)
+}
+
+/// Plays the files through [`DebugRC::run_tases_fastforward`], one call per file unless
+/// `run_as_merged_file` is set, so that `cancel` can be checked between files. A playing TAS is
+/// stopped from the progress callback once `cancel` is cancelled.
+fn run_tases_fastforward(
+    debugrc: &DebugRC,
+    tas_files: &[PreparedFile],
+    speedup: f32,
+    mut run_as_merged_file: bool,
+    cancel: &CancellationToken,
+    mut progress: impl FnMut(PlayTasProgress),
+) -> Result<()> {
+    ensure!(!tas_files.is_empty(), "Tried to run zero TAS files");
+
+    // celestedebugrc would fall back to playing them one by one itself, without a chance to
+    // stop between the files
+    if run_as_merged_file {
+        let enforce_legal = tas_files.iter().any(|file| {
+            let content = std::fs::read_to_string(&file.path).unwrap_or_default();
+            content.contains("EnforceLegal") || content.contains("EnforceMaingame")
+        });
+
+        if enforce_legal {
+            eprintln!("File contains EnforceLegal, falling back to running TASes one by one");
+            run_as_merged_file = false;
+        }
+    }
+
+    let runs: Vec<&[PreparedFile]> = match run_as_merged_file {
+        true => vec![tas_files],
+        false => tas_files.chunks(1).collect(),
+    };
+    let total_files = runs.len();
+    for (i, run) in runs.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            return Err(Aborted.into());
+        }
+
+        let files: Vec<(&Path, String, (String, String))> = run
+            .iter()
+            .map(|file| {
+                (
+                    file.path.as_path(),
+                    file.name.clone(),
+                    file.decorate.clone(),
+                )
+            })
+            .collect();
+        let mut stopped = false;
+        debugrc.run_tases_fastforward(&files, speedup, run_as_merged_file, |status| {
+            if cancel.is_cancelled() && !stopped {
+                stopped = true;
+                if let Err(e) = debugrc.send_tas_keybind("Start") {
+                    eprintln!("Failed to stop TAS: {e}");
+                }
+            }
+            progress(PlayTasProgress {
+                current_file: i,
+                total_files,
+                ..status
+            });
+        })?;
+
+        if stopped {
+            let (_, decorate_end) = &run[run.len() - 1].decorate;
+            play_end_decoration(debugrc, decorate_end);
+            return Err(Aborted.into());
+        }
+    }
+    Ok(())
+}
+
+/// Plays the end decoration after a TAS was stopped, so that physics logging and TASRecorder
+/// don't keep running. Blocks until it finished playing.
+fn play_end_decoration(debugrc: &DebugRC, decorate_end: &str) {
+    let result = (|| {
+        let file = tempfile::Builder::new().suffix(".tas").tempfile()?;
+        std::fs::write(file.path(), format!("{decorate_end}\n1\n"))?;
+        debugrc.play_tas_sync(file.path(), |_| {})
+    })();
+    if let Err(e) = result {
+        eprintln!("Failed to disable physics logging: {e:?}");
+    }
+}
+
+/// A TAS file ready to be played by `run_tases_fastforward`.
+struct PreparedFile {
+    /// The file to play, which may be a temporary file
+    path: PathBuf,
+    name: String,
+    decorate: (String, String),
+    provenance: Provenance,
+}
+
+/// Decorates the TAS files with the physics logging commands, writing temporary files
+/// for git-diff trimmed or original versions.
+fn prepare_files(
+    paths: &[PathBuf],
+    options: &RecordOptions,
+    tmp_files: &mut Vec<PathBuf>,
+) -> Result<Vec<PreparedFile>> {
+    let mut files = Vec::with_capacity(paths.len());
+
+    let enable_base = "Set,ConsistencyTracker.LogPhysicsEnabled,true";
+    let disable_base = "Set,ConsistencyTracker.LogPhysicsEnabled,false";
+
+    let record_ghost = options.record_git_tree && options.enable_tas_recorder;
+
+    let decorate = {
+        let mut decorate_begin = enable_base.to_owned();
+        let mut decorate_end = disable_base.to_owned();
+        if options.enable_tas_recorder {
+            decorate_begin.push('\n');
+            decorate_begin.push_str("StartRecording");
+            decorate_end.push('\n');
+            decorate_end.push_str("StopRecording");
+        }
+        if record_ghost {
+            decorate_begin.push('\n');
+            decorate_begin.push_str("StartGhostReplay");
+        }
+        (decorate_begin, decorate_end)
+    };
+
+    let decorate_orig = {
+        let mut decorate_orig_begin = enable_base.to_owned();
+        let mut decorate_orig_end = disable_base.to_owned();
+        if record_ghost {
+            decorate_orig_begin.push_str("\nStartGhostRecording");
+            decorate_orig_end.push_str("\nStopGhostRecording");
+        }
+        (decorate_orig_begin, decorate_orig_end)
+    };
+
+    let recorded_at = chrono::Local::now().to_rfc3339();
+    let provenance = |path: &Path, commit: Option<String>, variant| Provenance {
+        tas_file: path.to_owned(),
+        commit,
+        variant,
+        recorded_at: recorded_at.clone(),
+    };
+
+    for path in paths {
+        let path = path.clone();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .with_context(|| format!("invalid TAS path {}", path.display()))?
+            .to_owned();
+
+        let parent = path.parent().unwrap_or(Path::new("/"));
+
+        if options.only_record_changes {
+            let old_new = with_old_new(&path, |commit, old, new| {
+                (short_id(&commit), old.to_owned(), new)
+            })?;
+            match old_new {
+                Some((commit, old, new)) => {
+                    if options.record_git_tree {
+                        let only_diff_reverse =
+                            physics_log_in_diff(&new, &old, decorate_orig.clone());
+                        let tmpfile = write_to_temp_in(&only_diff_reverse, parent, tmp_files)?;
+
+                        files.push(PreparedFile {
+                            path: tmpfile,
+                            name: format!("{name} original"),
+                            decorate: decorate_orig.clone(),
+                            provenance: provenance(&path, Some(commit.clone()), Variant::Original),
+                        });
+                    }
+
+                    let only_diff = physics_log_in_diff(&old, &new, decorate.clone());
+                    let tmpfile = write_to_temp_in(&only_diff, parent, tmp_files)?;
+                    files.push(PreparedFile {
+                        path: tmpfile,
+                        name,
+                        decorate: decorate.clone(),
+                        provenance: provenance(&path, Some(commit), Variant::Changes),
+                    });
+                }
+                None => files.push(PreparedFile {
+                    provenance: provenance(&path, None, Variant::Full),
+                    path,
+                    name,
+                    decorate: decorate.clone(),
+                }),
+            }
+        } else {
+            let mut commit = None;
+            if options.record_git_tree {
+                if let Ok(Some((commit_id, old_data))) = is_git_changed(&path) {
+                    let tmpfile = write_to_temp_in(&old_data, parent, tmp_files)?;
+                    files.push(PreparedFile {
+                        path: tmpfile,
+                        name: format!("{name} original"),
+                        decorate: decorate_orig.clone(),
+                        provenance: provenance(&path, Some(commit_id.clone()), Variant::Original),
+                    });
+                    commit = Some(commit_id);
+                }
+            }
+
+            let commit = commit.or_else(|| git_head_commit(&path));
+            files.push(PreparedFile {
+                provenance: provenance(&path, commit, Variant::Full),
+                path,
+                name,
+                decorate: decorate.clone(),
+            });
+        }
+    }
+
+    Ok(files)
+}
+
+pub fn tas_recorder_output_dir(celeste: &CelesteInstallation) -> Option<PathBuf> {
+    let settings = celeste.mod_settings("TASRecorder").ok()?;
+    let output_dir = settings["OutputDirectory"].as_str()?;
+    Some(celeste.path.join(output_dir))
+}
+
+pub fn check_required_mods(debugrc: &DebugRC) -> (bool, Option<String>, bool) {
+    match debugrc.list_mods() {
+        Ok(mods) => {
+            let required_mods = ["CelesteTAS", "ConsistencyTracker"];
+            let mut msg = String::new();
+            for mod_name in required_mods {
+                if !mods.iter().any(|m| m == mod_name) {
+                    let _ = writeln!(&mut msg, "Mod `{}` is not installed. ", mod_name);
+                }
+            }
+            let tas_recorder_installed = mods.iter().any(|m| m == "TASRecorder");
+            (
+                true,
+                (!msg.is_empty()).then_some(msg),
+                tas_recorder_installed,
+            )
+        }
+        Err(_) => (false, None, true),
+    }
+}
+
+fn write_to_temp_in(
+    data: &str,
+    tmp_dir: &Path,
+    tmp_files: &mut Vec<PathBuf>,
+) -> Result<PathBuf, anyhow::Error> {
+    let name: String = "tmp_"
+        .chars()
+        .chain(std::iter::repeat_with(fastrand::alphabetic).take(12))
+        .chain(".tas".chars())
+        .collect::<String>();
+    let file = tmp_dir.join(&name);
+
+    std::fs::write(&file, data)?;
+    tmp_files.push(file.clone());
+
+    Ok(file)
+}
+
+fn with_old_new<T>(
+    path: &Path,
+    f: impl Fn(gix::Commit<'_>, &str, String) -> T,
+) -> Result<Option<T>> {
+    let Some(parent) = path.parent() else {
+        return Ok(None);
+    };
+
+    let Ok(git) = gix::discover(parent) else {
+        return Ok(None);
+    };
+
+    let relative_path = path
+        .strip_prefix(git.workdir().context("repo has no workdir")?)
+        .context("path not in repo??")?;
+
+    let head = git.head_commit()?;
+    let tree = head.tree()?;
+
+    let object = tree
+        .lookup_entry_by_path(relative_path)?
+        .context("path not in repo?")?
+        .object()?;
+
+    let data_new = std::fs::read_to_string(path)?;
+    let data_old = std::str::from_utf8(&object.data)?;
+
+    Ok(Some(f(head, data_old, data_new)))
+}
+
+fn short_id(commit: &gix::Commit<'_>) -> String {
+    commit
+        .short_id()
+        .map_or_else(|_| format!("{commit:?}"), |prefix| prefix.to_string())
+}
+
+/// returns (CommitPrefix, OldData)
+pub fn is_git_changed(path: &Path) -> Result<Option<(String, String)>> {
+    with_old_new(path, |commit, old, new| {
+        let changed = old != new.replace("\r\n", "\n");
+        changed.then_some((short_id(&commit), old.to_owned()))
+    })
+    .map(Option::flatten)
+}
+
+/// Short id of the HEAD commit of the repository the file is tracked in.
+fn git_head_commit(path: &Path) -> Option<String> {
+    with_old_new(path, |commit, _, _| short_id(&commit))
+        .ok()
+        .flatten()
+}
+
+fn physics_log_in_diff(old: &str, new: &str, decorate: (String, String)) -> String {
+    let mut first_line_changed = None;
+    let mut first_line_changed_rev = None;
+    let new_line_count = new.lines().count();
+
+    let care_about_line = |line: &str| {
+        let line = line.trim_start();
+        !line.starts_with('#')
+            && !line.starts_with("FileTime")
+            && !line.starts_with("ChapterTime")
+            && !line.starts_with("RecordCount")
+    };
+
+    for (i, (old, new)) in old.lines().zip(new.lines()).enumerate() {
+        if first_line_changed.is_none() && old != new {
+            if !care_about_line(old) && !care_about_line(new) {
+                continue;
+            }
+            first_line_changed = Some(i);
+        }
+    }
+
+    for (i, (old, new)) in old.lines().rev().zip(new.lines().rev()).enumerate() {
+        if first_line_changed_rev.is_none() && old != new {
+            if !care_about_line(old) && !care_about_line(new) {
+                continue;
+            }
+            first_line_changed_rev = Some(i);
+        }
+    }
+
+    let (Some(first_line_changed), Some(first_line_changed_rev)) =
+        (first_line_changed, first_line_changed_rev)
+    else {
+        return new.into();
+    };
+
+    let (enable, disable) = decorate;
+
+    let mut out = String::with_capacity(new.len() + disable.len() * 3);
+    out.push_str(&disable);
+    out.push('\n');
+
+    for (i, line) in new.lines().enumerate() {
+        if i == first_line_changed {
+            out.push_str(&enable);
+            out.push('\n');
+        }
+        out.push_str(line);
+        out.push('\n');
+        if i == new_line_count - 1 - first_line_changed_rev {
+            out.push_str(&disable);
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+#[test]
+fn hi() {
+    let enable = "Set,ConsistencyTracker.LogPhysicsEnabled,true";
+    let disable = "Set,ConsistencyTracker.LogPhysicsEnabled,false";
+
+    let result = physics_log_in_diff(
+        "# Start
+190
+1,J
+# lvl_1
+2,J
+# lvl_2
+3,J
+15,U,R,X
+10,L
+# lvl_end
+4,J
+ChapterTime:
+",
+        "# Start
+190
+1,J
+# lvl_1
+2,J
+# lvl_2
+10,U,R,X
+2,R,K,G
+10,L
+# lvl_end
+4,J
+ChapterTime:
+",
+        (enable.into(), disable.into()),
+    );
+    println!("{}", result);
+    assert_eq!(
+        result,
+        "Set,ConsistencyTracker.LogPhysicsEnabled,false
+# Start
+190
+1,J
+# lvl_1
+2,J
+# lvl_2
+Set,ConsistencyTracker.LogPhysicsEnabled,true
+10,U,R,X
+2,R,K,G
+Set,ConsistencyTracker.LogPhysicsEnabled,false
+10,L
+# lvl_end
+4,J
+ChapterTime:
+"
+    )
+}
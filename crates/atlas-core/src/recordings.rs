@@ -0,0 +1,228 @@
+use anyhow::{Context, Result};
+use celesteloader::cct_physics_inspector::PhysicsInspector;
+use chrono::{DateTime, FixedOffset};
+use indexmap::IndexMap;
+
+use std::collections::HashSet;
+
+use crate::archive::{Archive, POSITION_LOG_SUFFIX, ROOM_LAYOUT_SUFFIX};
+use crate::labels::{Label, LabelIndex};
+use crate::provenance::{Provenance, ProvenanceIndex};
+use crate::sidecar;
+
+/// A single CCT physics recording.
+#[derive(Clone, Debug)]
+pub struct RecordingInfo {
+    /// Index of the recording in CCT's `recent-recordings` folder, or in the [`Archive`]
+    pub index: u32,
+    /// Identifies the recording across changes of its index, see [`sidecar::recording_key`]
+    pub key: String,
+    pub recording_started: Option<DateTime<FixedOffset>>,
+    pub start_room: String,
+    /// Debug names of the rooms the recording went through, in order
+    pub rooms: Vec<String>,
+    pub frame_count: u32,
+    /// The TAS file this was recorded from, if it was recorded through Atlas
+    pub provenance: Option<Provenance>,
+    pub label: Label,
+    /// Whether a copy of the recording is in the [`Archive`]
+    pub pinned: bool,
+}
+
+/// Recordings of the same map, in order of their CCT index.
+#[derive(Clone, Debug)]
+pub struct MapGroup {
+    /// Empty for recordings of old CCT versions, which didn't record the map bin
+    pub map_bin: String,
+    /// Chapter and side as written by CCT, like `Forsaken City B-Side`
+    pub chapter_name: String,
+    /// `A-Side`, `B-Side` or `C-Side`, see [`crate::names::MapNames::chapter_name`]
+    pub side_name: String,
+    /// Whether the recordings are read from the [`Archive`]
+    pub archived: bool,
+    pub recordings: Vec<RecordingInfo>,
+}
+
+impl MapGroup {
+    pub fn is_old_cct(&self) -> bool {
+        self.map_bin.is_empty()
+    }
+}
+
+/// Reads CCT's recent recordings and groups them by map, followed by the groups of the archived ones.
+pub fn read_recordings(
+    physics_inspector: &PhysicsInspector,
+    archive: Option<&Archive>,
+) -> Result<Vec<MapGroup>> {
+    let pinned = match archive {
+        Some(archive) => archive.keys()?,
+        None => HashSet::new(),
+    };
+
+    let mut groups = group_recordings(physics_inspector, false, &pinned)?;
+    if let Some(archive) = archive {
+        groups.extend(group_recordings(
+            archive.physics_inspector(),
+            true,
+            &pinned,
+        )?);
+    }
+    Ok(groups)
+}
+
+/// Deletes some of CCT's recent recordings, then renumbers the remaining ones so their indices
+/// stay contiguous like CCT expects when it rotates them.
+///
+/// A recording that is still in progress at index 0, which only has a position log so far,
+/// is kept where it is.
+pub fn delete_recordings(physics_inspector: &PhysicsInspector, indices: &[u32]) -> Result<()> {
+    let dir = &physics_inspector.recent_recordings;
+    let path = |i: u32, suffix: &str| dir.join(format!("{i}{suffix}"));
+
+    for &i in indices {
+        for suffix in [ROOM_LAYOUT_SUFFIX, POSITION_LOG_SUFFIX] {
+            let path = path(i, suffix);
+            if path.exists() {
+                std::fs::remove_file(&path)
+                    .with_context(|| format!("failed to delete {}", path.display()))?;
+            }
+        }
+    }
+
+    let in_progress =
+        !path(0, ROOM_LAYOUT_SUFFIX).exists() && path(0, POSITION_LOG_SUFFIX).exists();
+    let mut remaining: Vec<u32> = physics_inspector
+        .recent_recordings()?
+        .into_iter()
+        .map(|(i, _)| i)
+        .collect();
+    remaining.sort();
+
+    // moving in ascending order only ever moves onto indices that are already free
+    let first = u32::from(in_progress);
+    for (new, old) in (first..).zip(remaining) {
+        if new == old {
+            continue;
+        }
+        for suffix in [POSITION_LOG_SUFFIX, ROOM_LAYOUT_SUFFIX] {
+            std::fs::rename(path(old, suffix), path(new, suffix)).with_context(|| {
+                format!(
+                    "failed to move recording {old} to {new} in {}",
+                    dir.display()
+                )
+            })?;
+        }
+    }
+
+    let keys = physics_inspector
+        .recent_recordings()?
+        .iter()
+        .map(|(_, layout)| sidecar::recording_key(layout))
+        .collect();
+    LabelIndex::load(physics_inspector)?.save(physics_inspector, &keys)?;
+    ProvenanceIndex::load(physics_inspector)?.save(physics_inspector, &keys)?;
+
+    Ok(())
+}
+
+fn group_recordings(
+    physics_inspector: &PhysicsInspector,
+    archived: bool,
+    pinned: &HashSet<String>,
+) -> Result<Vec<MapGroup>> {
+    let mut recent_recordings = physics_inspector.recent_recordings()?;
+    recent_recordings.sort_by_key(|a| a.0);
+
+    let provenance_index = ProvenanceIndex::load(physics_inspector).unwrap_or_else(|e| {
+        eprintln!("Failed to read provenance index: {e:?}");
+        ProvenanceIndex::default()
+    });
+    let label_index = LabelIndex::load(physics_inspector).unwrap_or_else(|e| {
+        eprintln!("Failed to read labels: {e:?}");
+        LabelIndex::default()
+    });
+
+    let mut recordings = IndexMap::<_, Vec<_>>::new();
+    for (i, layout) in recent_recordings {
+        let old_cct = layout.map_bin.is_none();
+        let key = sidecar::recording_key(&layout);
+        let provenance = provenance_index.get(&key).cloned();
+        let label = label_index.get(&key).cloned().unwrap_or_default();
+        let is_pinned = pinned.contains(&key);
+
+        if layout.frame_count == 1 {
+            continue;
+        }
+
+        let is_vanilla = layout.sid.is_some_and(|sid| sid.starts_with("Celeste/"));
+        let map_bin = layout.map_bin.unwrap_or_default();
+        let map_bin = match is_vanilla && !old_cct {
+            true => format!("Celeste/{map_bin}"),
+            false => map_bin,
+        };
+
+        let rooms: Vec<String> = layout
+            .rooms
+            .iter()
+            .map(|room| room.debug_room_name.clone())
+            .collect();
+        let start_room = rooms.first().cloned().unwrap_or_default();
+
+        recordings
+            .entry((map_bin, layout.chapter_name, layout.side_name))
+            .or_default()
+            .push(RecordingInfo {
+                index: i,
+                key,
+                recording_started: DateTime::parse_from_rfc3339(&layout.recording_started).ok(),
+                start_room,
+                rooms,
+                frame_count: layout.frame_count,
+                provenance,
+                label,
+                pinned: is_pinned,
+            });
+    }
+
+    Ok(recordings
+        .into_iter()
+        .map(
+            |((map_bin, chapter_name, side_name), recordings)| MapGroup {
+                map_bin,
+                chapter_name: match side_name.as_str() {
+                    "A-Side" => chapter_name,
+                    _ => format!("{chapter_name} {side_name}"),
+                },
+                side_name,
+                archived,
+                recordings,
+            },
+        )
+        .collect())
+}
+
+#[test]
+fn delete_and_compact() -> Result<()> {
+    let dir = crate::test_utils::temp_dir();
+    let physics_inspector = PhysicsInspector {
+        recent_recordings: dir.path().to_owned(),
+    };
+    for i in 0..5 {
+        crate::test_utils::write_recording(&physics_inspector, i, i, &i.to_string())?;
+    }
+
+    delete_recordings(&physics_inspector, &[0, 2])?;
+
+    let mut remaining: Vec<_> = physics_inspector
+        .recent_recordings()?
+        .into_iter()
+        .map(|(i, layout)| (i, layout.id))
+        .collect();
+    remaining.sort();
+    assert_eq!(remaining, [(0, 1), (1, 3), (2, 4)]);
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join(format!("1{POSITION_LOG_SUFFIX}")))?,
+        "3"
+    );
+    Ok(())
+}
@@ -0,0 +1,625 @@
+use annotate_celeste_map::{ColorMode, LineSettings};
+use anyhow::{bail, ensure, Context, Result};
+use celesteloader::map::{Bounds, Map, Pos};
+use celesteloader::{cct_physics_inspector::PhysicsInspector, CelesteInstallation};
+use celesterender::asset::{AssetDb, ModLookup};
+use celesterender::{CelesteRenderData, Layer, MapTileset, RenderMapSettings, RenderResult};
+use chrono::{DateTime, Local};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::panic::AssertUnwindSafe;
+use std::time::Instant;
+
+/// Parses the color modes offered in the render settings, case insensitively.
+pub fn color_mode_from_name(name: &str) -> Option<ColorMode> {
+    let color_mode = match name.to_ascii_lowercase().as_str() {
+        "gradient" => ColorMode::Gradient,
+        "random" => ColorMode::Random,
+        "state" => ColorMode::State,
+        "red" => ColorMode::Color([255, 0, 0, 255]),
+        _ => return None,
+    };
+    Some(color_mode)
+}
+
+/// Parses a layer name like `fgtiles`, `fg` or `entities`.
+pub fn layer_from_name(name: &str) -> Option<Layer> {
+    let layer = match name.to_ascii_lowercase().as_str() {
+        "fg" | "fgtiles" => Layer::TILES_FG,
+        "bg" | "bgtiles" => Layer::TILES_BG,
+        "entities" => Layer::ENTITIES,
+        "fgdecals" => Layer::DECALS_FG,
+        "bgdecals" => Layer::DECALS_BG,
+        "triggers" => Layer::TRIGGERS,
+        "all" => Layer::ALL,
+        _ => return None,
+    };
+    Some(layer)
+}
+
+/// File format of rendered maps
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OutputFormat {
+    Png,
+    /// Map as embedded raster image, recordings as vector paths
+    Svg,
+    /// Animated playback of the recordings, see [`crate::animation`]
+    Gif,
+    Apng,
+    /// Folder of numbered PNG frames
+    Frames,
+}
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 5] = [
+        OutputFormat::Png,
+        OutputFormat::Svg,
+        OutputFormat::Gif,
+        OutputFormat::Apng,
+        OutputFormat::Frames,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "PNG",
+            OutputFormat::Svg => "SVG",
+            OutputFormat::Gif => "GIF",
+            OutputFormat::Apng => "APNG",
+            OutputFormat::Frames => "Frames",
+        }
+    }
+
+    /// Parses a format name like `png` or `apng`, case insensitively.
+    pub fn from_name(name: &str) -> Option<Self> {
+        OutputFormat::ALL
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Empty for [`OutputFormat::Frames`], which is saved as a folder
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png | OutputFormat::Apng => "png",
+            OutputFormat::Svg => "svg",
+            OutputFormat::Gif => "gif",
+            OutputFormat::Frames => "",
+        }
+    }
+
+    pub fn is_animated(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Gif | OutputFormat::Apng | OutputFormat::Frames
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct RenderOptions {
+    pub line_settings: LineSettings,
+    pub layer: Layer,
+    pub only_include_visited_rooms: bool,
+    /// Draw every recording in its own color with a legend, see [`crate::comparison`]
+    pub compare: bool,
+    /// Part of the map the image is cropped to
+    pub region: RenderRegion,
+    /// Pixels around the region that are rendered too
+    pub region_padding: u32,
+}
+
+/// Part of a map to render.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum RenderRegion {
+    #[default]
+    Map,
+    /// Inclusive ranges of room names, see [`RenderRegion::parse`]
+    Rooms(Vec<(String, String)>),
+    /// A box in map pixels
+    Area {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl RenderRegion {
+    /// Parses rooms like `a-03..a-07, b-00` or an area like `x,y,width,height` in map pixels.
+    /// Ranges include every room whose name sorts between the two, comparing numbers by value.
+    /// Empty for the whole map.
+    pub fn parse(region: &str) -> Result<RenderRegion> {
+        let parts: Vec<&str> = region
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .collect();
+        if parts.is_empty() {
+            return Ok(RenderRegion::Map);
+        }
+
+        if let [x, y, width, height] = parts[..] {
+            if let (Ok(x), Ok(y), Ok(width), Ok(height)) =
+                (x.parse(), y.parse(), width.parse(), height.parse())
+            {
+                ensure!(width > 0 && height > 0, "the area {region} is empty");
+                return Ok(RenderRegion::Area {
+                    x,
+                    y,
+                    width,
+                    height,
+                });
+            }
+        }
+
+        let rooms = parts
+            .into_iter()
+            .map(|part| match part.split_once("..") {
+                Some((from, to)) => {
+                    let (from, to) = (from.trim(), to.trim());
+                    if from.is_empty() || to.is_empty() {
+                        bail!("room range {part} needs a first and last room");
+                    }
+                    Ok((from.to_owned(), to.to_owned()))
+                }
+                None => Ok((part.to_owned(), part.to_owned())),
+            })
+            .collect::<Result<_>>()?;
+        Ok(RenderRegion::Rooms(rooms))
+    }
+
+    fn includes_room(ranges: &[(String, String)], room: &str) -> bool {
+        ranges.iter().any(|(from, to)| {
+            compare_room_names(from, room).is_le() && compare_room_names(room, to).is_le()
+        })
+    }
+
+    /// Area of `map` to render with `padding` around it, `None` for the whole map.
+    fn bounds(&self, map: &Map, padding: u32) -> Result<Option<Bounds>> {
+        let bounds = match self {
+            RenderRegion::Map => return Ok(None),
+            RenderRegion::Rooms(ranges) => map
+                .rooms
+                .iter()
+                .filter(|room| {
+                    RenderRegion::includes_room(ranges, room.name.trim_start_matches("lvl_"))
+                })
+                .map(|room| room.bounds)
+                .reduce(Bounds::join)
+                .context("no room of the map is in the render region")?,
+            &RenderRegion::Area {
+                x,
+                y,
+                width,
+                height,
+            } => Bounds {
+                position: Pos { x, y },
+                size: (width, height),
+            },
+        };
+
+        let padding = padding as i32;
+        Ok(Some(Bounds {
+            position: Pos {
+                x: bounds.position.x - padding,
+                y: bounds.position.y - padding,
+            },
+            size: (
+                bounds.size.0 + 2 * padding as u32,
+                bounds.size.1 + 2 * padding as u32,
+            ),
+        }))
+    }
+}
+
+/// Orders room names like `a-9` before `a-10`, comparing the numbers in them by value.
+fn compare_room_names(a: &str, b: &str) -> Ordering {
+    #[derive(PartialEq, Eq, PartialOrd, Ord)]
+    enum Chunk {
+        Number(u64),
+        Text(String),
+    }
+    let chunks = |name: &str| {
+        let mut chunks = Vec::new();
+        let mut chars = name.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            let is_digit = c.is_ascii_digit();
+            let mut chunk = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_digit() == is_digit) {
+                chunk.push(c);
+            }
+            chunks.push(match is_digit {
+                true => Chunk::Number(chunk.parse().unwrap_or(u64::MAX)),
+                false => Chunk::Text(chunk.to_lowercase()),
+            });
+        }
+        chunks
+    };
+    chunks(a).cmp(&chunks(b))
+}
+
+fn intersection(a: Bounds, b: Bounds) -> Option<Bounds> {
+    let x = a.position.x.max(b.position.x);
+    let y = a.position.y.max(b.position.y);
+    let r = a.r().min(b.r());
+    let b = a.b().min(b.b());
+    (r > x && b > y).then(|| Bounds {
+        position: Pos { x, y },
+        size: ((r - x) as u32, (b - y) as u32),
+    })
+}
+
+pub struct RenderState {
+    celeste: CelesteInstallation,
+    pub(crate) physics_inspector: PhysicsInspector,
+    asset_db: AssetDb<ModLookup>,
+    render_data: CelesteRenderData,
+}
+impl RenderState {
+    pub fn new(celeste: &CelesteInstallation) -> Result<Self> {
+        Ok(RenderState {
+            celeste: celeste.clone(),
+            physics_inspector: PhysicsInspector::new(celeste),
+            asset_db: AssetDb::new(ModLookup::all_mods(celeste)?),
+            render_data: CelesteRenderData::base(celeste)?,
+        })
+    }
+
+    /// Reads the recordings from `physics_inspector` instead of CCT's recent recordings,
+    /// e.g. from the [`Archive`](crate::archive::Archive).
+    pub fn set_recordings_source(&mut self, physics_inspector: PhysicsInspector) {
+        self.physics_inspector = physics_inspector;
+    }
+
+    /// Loads the map and its tileset, then renders it with `render`, like
+    /// [`celesterender::render_map_bin`] but with access to the map before rendering.
+    fn render(
+        &mut self,
+        map_bin: &str,
+        render: impl FnOnce(&CelesteRenderData, &mut AssetDb<ModLookup>, &Map) -> Result<RenderResult>,
+    ) -> Result<(RenderResult, Map)> {
+        catch(|| {
+            let (map, mut archive) = self.celeste.find_map_by_map_bin(map_bin)?;
+            match &mut archive {
+                Some(archive) => self
+                    .render_data
+                    .load_map_tileset(&self.celeste, archive, &map)?,
+                None => self.render_data.map_tileset = MapTileset::vanilla(&self.celeste)?,
+            }
+
+            let result = render(&self.render_data, &mut self.asset_db, &map)?;
+            Ok((result, map))
+        })
+    }
+}
+
+/// Renders a single map and annotates it with the paths of the given CCT recordings.
+///
+/// `labels` are only shown in the legend of comparison renders.
+pub fn render_map(
+    state: &mut RenderState,
+    map_bin: &str,
+    name: &str,
+    recordings: &[u32],
+    labels: &HashMap<u32, String>,
+    options: RenderOptions,
+    on_status_update: &impl Fn(String),
+) -> Result<RenderResult> {
+    let (mut result, _map) =
+        render_background(state, map_bin, name, recordings, &options, on_status_update)?;
+
+    on_status_update("Annotating...".into());
+    let start_annotate = Instant::now();
+    if options.compare {
+        crate::comparison::annotate_comparison(
+            &mut result.image,
+            &state.physics_inspector,
+            recordings,
+            labels,
+            result.bounds,
+            options.line_settings,
+        )?;
+    } else {
+        annotate_celeste_map::annotate_cct_recording_skia(
+            &mut result.image,
+            &state.physics_inspector,
+            recordings.iter().copied(),
+            result.bounds,
+            options.line_settings,
+        )?;
+    }
+    eprintln!(
+        "Annotated map {map_bin} in {:.2}ms",
+        start_annotate.elapsed().as_millis()
+    );
+
+    Ok(result)
+}
+
+/// Renders a single map as an SVG document, see [`crate::svg::annotated_map_svg`].
+///
+/// [`RenderOptions::compare`] isn't supported and falls back to the regular line colors.
+pub fn render_map_svg(
+    state: &mut RenderState,
+    map_bin: &str,
+    name: &str,
+    recordings: &[u32],
+    options: RenderOptions,
+    on_status_update: &impl Fn(String),
+) -> Result<String> {
+    let (mut result, map) =
+        render_background(state, map_bin, name, recordings, &options, on_status_update)?;
+
+    on_status_update("Annotating...".into());
+    let bounds = result.bounds;
+    let mut background = Vec::new();
+    result.encode_png(&mut background, celesterender::Compression::Balanced)?;
+
+    crate::svg::annotated_map_svg(
+        &background,
+        bounds,
+        &map,
+        &state.physics_inspector,
+        recordings,
+        options.line_settings,
+    )
+}
+
+pub(crate) fn render_background(
+    state: &mut RenderState,
+    map_bin: &str,
+    name: &str,
+    recordings: &[u32],
+    options: &RenderOptions,
+    on_status_update: &impl Fn(String),
+) -> Result<(RenderResult, Map)> {
+    let mut only_include_visited_rooms = options.only_include_visited_rooms;
+    let visited_rooms = if only_include_visited_rooms {
+        cct_visited_rooms(recordings, &state.physics_inspector).unwrap_or_else(|e| {
+            eprintln!("Couldn't read room layouts, falling back to including all rooms: {e}");
+            only_include_visited_rooms = false;
+            Default::default()
+        })
+    } else {
+        HashSet::new()
+    };
+
+    let start_render = Instant::now();
+    let (result, map) = state
+        .render(map_bin, |render_data, asset_db, map| {
+            let region = options.region.bounds(map, options.region_padding)?;
+            let render_settings = RenderMapSettings {
+                layer: options.layer,
+                include_room: &|room| {
+                    (!only_include_visited_rooms
+                        || visited_rooms.contains(room.name.trim_start_matches("lvl_")))
+                        && region.is_none_or(|region| intersection(region, room.bounds).is_some())
+                },
+                status_update: &|room_current, room_total| {
+                    on_status_update(format!("Rendering [{room_current}/{room_total}]"))
+                },
+            };
+            let mut result = celesterender::render(render_data, asset_db, map, render_settings)?;
+
+            if let Some(region) = region {
+                let crop = intersection(region, result.bounds)
+                    .context("the render region doesn't contain any rendered room")?;
+                let rect = tiny_skia::IntRect::from_xywh(
+                    crop.position.x - result.bounds.position.x,
+                    crop.position.y - result.bounds.position.y,
+                    crop.size.0,
+                    crop.size.1,
+                )
+                .context("invalid render region")?;
+                result.image = result
+                    .image
+                    .clone_rect(rect)
+                    .context("invalid render region")?;
+                result.bounds = crop;
+            }
+            Ok(result)
+        })
+        .with_context(|| format!("failed to render {name}"))?;
+
+    // let size_filled = map.rooms.iter().map(|room| room.bounds.area()).sum::<f32>();
+    // let size = result.bounds.area();
+    // let density = size_filled / size;
+
+    // let width = Some(2.0);
+    // let width = width.unwrap_or_else(|| if density > 0.5 { 8.0 } else { 3.0 });
+
+    eprintln!(
+        "Rendered map {map_bin} in {:.2}ms",
+        start_render.elapsed().as_millis(),
+    );
+
+    if !result.unknown_entities.is_empty() {
+        let mut unknown = result.unknown_entities.iter().collect::<Vec<_>>();
+        unknown.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+
+        eprintln!(
+            "  Found {} unknown entities: ({} ...)",
+            unknown.len(),
+            unknown
+                .iter()
+                .take(5)
+                .fold(String::new(), |mut acc, (num, name)| {
+                    let _ = write!(&mut acc, "{num} {name} ");
+                    acc
+                })
+        );
+    }
+
+    Ok((result, map))
+}
+
+/// Expands a file name template like `{map_bin}_{date}_{recordings}.png`.
+///
+/// Supported placeholders are `{map_bin}`, `{chapter}`, `{recordings}`, `{labels}`, `{date}`
+/// and `{time}`, where `{labels}` falls back to the index of recordings without a label.
+/// Characters which aren't allowed in file names are replaced by `_`. The extension of `format`
+/// is appended, replacing the extension of another output format if the template has one.
+/// For [`OutputFormat::Frames`] the result is used as folder name.
+pub fn expand_file_name_template(
+    template: &str,
+    map_bin: &str,
+    chapter_name: &str,
+    recordings: &[u32],
+    labels: &HashMap<u32, String>,
+    now: DateTime<Local>,
+    format: OutputFormat,
+) -> String {
+    let labels = recordings
+        .iter()
+        .map(|i| labels.get(i).cloned().unwrap_or_else(|| i.to_string()))
+        .collect::<Vec<_>>()
+        .join("-");
+    let recordings = recordings
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("-");
+
+    let mut name = template
+        .replace("{map_bin}", map_bin)
+        .replace("{chapter}", chapter_name)
+        .replace("{recordings}", &recordings)
+        .replace("{labels}", &labels)
+        .replace("{date}", &now.format("%Y-%m-%d").to_string())
+        .replace("{time}", &now.format("%H-%M-%S").to_string())
+        .replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_");
+
+    if let Some((stem, extension)) = name.rsplit_once('.') {
+        let is_output_extension = OutputFormat::ALL.iter().any(|other| {
+            !other.extension().is_empty() && other.extension().eq_ignore_ascii_case(extension)
+        });
+        if is_output_extension && !format.extension().eq_ignore_ascii_case(extension) {
+            name.truncate(stem.len());
+        }
+    }
+    if !format.extension().is_empty()
+        && !name
+            .to_ascii_lowercase()
+            .ends_with(&format!(".{}", format.extension()))
+    {
+        name.push('.');
+        name.push_str(format.extension());
+    }
+    name
+}
+
+fn cct_visited_rooms(
+    recordings: &[u32],
+    physics_inspector: &PhysicsInspector,
+) -> Result<HashSet<String>> {
+    let mut rooms = HashSet::new();
+    for &recording in recordings {
+        let layout = physics_inspector.room_layout(recording)?;
+        rooms.extend(layout.rooms.into_iter().map(|room| room.debug_room_name));
+    }
+    Ok(rooms)
+}
+
+fn catch<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    match std::panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(val)) => Ok(val),
+        Ok(Err(val)) => Err(val),
+        Err(e) => {
+            if let Some(s) = e.downcast_ref::<String>() {
+                Err(anyhow::anyhow!("panic: {s}"))
+            } else if let Some(s) = e.downcast_ref::<&str>() {
+                Err(anyhow::anyhow!("panic: {s}"))
+            } else {
+                Err(anyhow::anyhow!("panicked"))
+            }
+        }
+    }
+}
+
+#[test]
+fn file_name_template() {
+    let now = chrono::TimeZone::with_ymd_and_hms(&Local, 2024, 3, 9, 14, 5, 0).unwrap();
+
+    assert_eq!(
+        expand_file_name_template(
+            "{map_bin}_{date}_{time}_{recordings}",
+            "Celeste/1-ForsakenCity",
+            "Forsaken City",
+            &[3, 4],
+            &HashMap::new(),
+            now,
+            OutputFormat::Png,
+        ),
+        "Celeste_1-ForsakenCity_2024-03-09_14-05-00_3-4.png"
+    );
+    assert_eq!(
+        expand_file_name_template(
+            "{chapter}: {unknown}.PNG",
+            "",
+            "Forsaken City",
+            &[],
+            &HashMap::new(),
+            now,
+            OutputFormat::Png,
+        ),
+        "Forsaken City_ {unknown}.PNG"
+    );
+    assert_eq!(
+        expand_file_name_template(
+            "{map_bin}.png",
+            "1-ForsakenCity",
+            "",
+            &[],
+            &HashMap::new(),
+            now,
+            OutputFormat::Svg
+        ),
+        "1-ForsakenCity.svg"
+    );
+    assert_eq!(
+        expand_file_name_template(
+            "{map_bin}.png",
+            "1-ForsakenCity",
+            "",
+            &[],
+            &HashMap::new(),
+            now,
+            OutputFormat::Frames
+        ),
+        "1-ForsakenCity"
+    );
+    assert_eq!(
+        expand_file_name_template(
+            "{labels}",
+            "1-ForsakenCity",
+            "",
+            &[3, 4],
+            &HashMap::from([(4, "new route".to_owned())]),
+            now,
+            OutputFormat::Png
+        ),
+        "3-new route.png"
+    );
+}
+
+#[test]
+fn render_region() {
+    assert_eq!(RenderRegion::parse(" ").unwrap(), RenderRegion::Map);
+    assert_eq!(
+        RenderRegion::parse("-100, 20, 320,180").unwrap(),
+        RenderRegion::Area {
+            x: -100,
+            y: 20,
+            width: 320,
+            height: 180
+        }
+    );
+    assert!(RenderRegion::parse("0,0,0,180").is_err());
+    assert!(RenderRegion::parse("a-03..").is_err());
+
+    let RenderRegion::Rooms(ranges) = RenderRegion::parse("a-03..a-10, b-00").unwrap() else {
+        panic!("not parsed as rooms");
+    };
+    let includes = |room| RenderRegion::includes_room(&ranges, room);
+    assert!(includes("a-03") && includes("a-9") && includes("A-10") && includes("b-00"));
+    assert!(!includes("a-02") && !includes("a-11") && !includes("b-01"));
+}
@@ -0,0 +1,79 @@
+//! Sidecar files stored next to CCT's recordings, with a JSON entry per recording.
+//!
+//! CCT shifts the indices of its recordings whenever a new one is made, so entries are keyed by
+//! [`recording_key`] instead of the index.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use celesteloader::cct_physics_inspector::{CCTRoomLayout, PhysicsInspector};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Key identifying a recording independent of its current CCT index.
+pub fn recording_key(layout: &CCTRoomLayout) -> String {
+    format!("{}@{}", layout.id, layout.recording_started)
+}
+
+/// Value stored per recording in a [`Sidecar`].
+pub trait SidecarEntry: Serialize + DeserializeOwned {
+    /// Name of the file next to the recordings
+    const FILE_NAME: &'static str;
+}
+
+/// Entries by [`recording_key`], stored in [`SidecarEntry::FILE_NAME`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Sidecar<T> {
+    recordings: BTreeMap<String, T>,
+}
+
+impl<T> Default for Sidecar<T> {
+    fn default() -> Self {
+        Sidecar {
+            recordings: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: SidecarEntry> Sidecar<T> {
+    fn path(physics_inspector: &PhysicsInspector) -> PathBuf {
+        physics_inspector.recent_recordings.join(T::FILE_NAME)
+    }
+
+    /// Reads the file, which is empty if it wasn't written yet.
+    pub fn load(physics_inspector: &PhysicsInspector) -> Result<Self> {
+        let path = Self::path(physics_inspector);
+        if !path.exists() {
+            return Ok(Sidecar::default());
+        }
+        let data =
+            std::fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_slice(&data).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// Writes the file, dropping entries of recordings that no longer exist.
+    pub fn save(
+        &mut self,
+        physics_inspector: &PhysicsInspector,
+        existing_keys: &HashSet<String>,
+    ) -> Result<()> {
+        self.recordings.retain(|key, _| existing_keys.contains(key));
+
+        let path = Self::path(physics_inspector);
+        let data = serde_json::to_vec_pretty(self)?;
+        std::fs::write(&path, data).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&T> {
+        self.recordings.get(key)
+    }
+
+    pub fn insert(&mut self, key: String, value: T) {
+        self.recordings.insert(key, value);
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.recordings.remove(key);
+    }
+}
@@ -0,0 +1,265 @@
+//! Per-room splits of a recording, read from the room bounds in CCT's room layout and the
+//! frames where the position log enters a new room.
+
+use std::fmt::Write;
+
+use anyhow::Result;
+use celesteloader::cct_physics_inspector::{CCTRoom, PhysicsInspector};
+
+/// Time spent in a room until the next room was entered.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomSplit {
+    pub room: String,
+    /// How often the room was entered before, so rooms visited twice can be told apart
+    pub visit: u32,
+    /// Frame of the recording the room was entered on
+    pub entry_frame: u32,
+    pub frames: u32,
+}
+
+impl RoomSplit {
+    #[cfg(test)]
+    pub(crate) fn new(room: &str, visit: u32, entry_frame: u32, frames: u32) -> RoomSplit {
+        RoomSplit {
+            room: room.into(),
+            visit,
+            entry_frame,
+            frames,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self.visit {
+            0 => self.room.clone(),
+            visit => format!("{} ({})", self.room, visit + 1),
+        }
+    }
+
+    /// Frames since the start of the recording until the room was left.
+    pub fn cumulative(&self, splits: &[RoomSplit]) -> u32 {
+        let start = splits.first().map_or(0, |first| first.entry_frame);
+        self.entry_frame + self.frames - start
+    }
+}
+
+/// How far ahead of the player, in the direction of movement, the room is looked up on the first
+/// frame in a room. On that frame the player is still at the edge of the room it left, these are
+/// about the width and height of Madeline's hitbox.
+const ROOM_LOOK_AHEAD_X: f32 = 8.;
+const ROOM_LOOK_AHEAD_Y: f32 = 12.;
+
+/// Splits recording `index` at every frame where it entered a new room.
+///
+/// Rooms are found by the position on the frames CCT flags as `FirstFrameInRoom`, or `?` if the
+/// position is outside of all rooms of the room layout.
+pub fn room_splits(physics_inspector: &PhysicsInspector, index: u32) -> Result<Vec<RoomSplit>> {
+    let layout = physics_inspector.room_layout(index)?;
+    let room_at = |x: f32, y: f32| {
+        layout.rooms.iter().find(|room: &&CCTRoom| {
+            let bounds = &room.level_bounds;
+            (bounds.x..bounds.x + bounds.w).contains(&x)
+                && (bounds.y..bounds.y + bounds.h).contains(&y)
+        })
+    };
+
+    // room name and first frame
+    let mut entries: Vec<(String, u32)> = Vec::new();
+    let mut end = 0;
+    for item in physics_inspector.position_log(index)? {
+        let item = item?;
+        let frame = item.frame_rta.saturating_sub(1);
+
+        if entries.is_empty() || item.flags.contains("FirstFrameInRoom") {
+            let room = room_at(
+                item.x + item.speed_x.signum() * ROOM_LOOK_AHEAD_X,
+                item.y + item.speed_y.signum() * ROOM_LOOK_AHEAD_Y,
+            )
+            .or_else(|| room_at(item.x, item.y))
+            .map_or("?", |room| room.debug_room_name.as_str());
+            entries.push((room.to_owned(), frame));
+        }
+        end = frame + 1;
+    }
+
+    let mut splits: Vec<RoomSplit> = Vec::with_capacity(entries.len());
+    for (i, (room, entry_frame)) in entries.iter().enumerate() {
+        let next = entries.get(i + 1).map_or(end, |(_, start)| *start);
+        let visit = splits.iter().filter(|split| split.room == *room).count() as u32;
+        splits.push(RoomSplit {
+            room: room.clone(),
+            visit,
+            entry_frame: *entry_frame,
+            frames: next.saturating_sub(*entry_frame),
+        });
+    }
+    Ok(splits)
+}
+
+/// A table of splits, as shown in the app and copied as Markdown or CSV.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SplitTable {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl SplitTable {
+    pub fn single(splits: &[RoomSplit]) -> SplitTable {
+        SplitTable {
+            headers: ["Room", "Entry frame", "Frames", "Total"]
+                .map(String::from)
+                .into(),
+            rows: splits
+                .iter()
+                .map(|split| {
+                    vec![
+                        split.name(),
+                        split.entry_frame.to_string(),
+                        split.frames.to_string(),
+                        frames_to_finaltime(split.cumulative(splits)),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    /// Rooms of `a` next to the same visit of the room in `b`, followed by the rooms only `b` went through.
+    pub fn side_by_side(
+        a_name: &str,
+        a: &[RoomSplit],
+        b_name: &str,
+        b: &[RoomSplit],
+    ) -> SplitTable {
+        let find = |splits: &'_ [RoomSplit], room: &RoomSplit| {
+            splits
+                .iter()
+                .find(|split| split.room == room.room && split.visit == room.visit)
+                .cloned()
+        };
+        let pairs = a
+            .iter()
+            .map(|room| (Some(room.clone()), find(b, room)))
+            .chain(
+                b.iter()
+                    .filter(|room| find(a, room).is_none())
+                    .map(|room| (None, Some(room.clone()))),
+            );
+
+        let columns = |split: &Option<RoomSplit>, splits: &[RoomSplit]| match split {
+            Some(split) => [
+                split.frames.to_string(),
+                frames_to_finaltime(split.cumulative(splits)),
+            ],
+            None => [String::new(), String::new()],
+        };
+        let rows = pairs
+            .map(|(split_a, split_b)| {
+                let name = split_a.as_ref().or(split_b.as_ref()).unwrap().name();
+                let delta = match (&split_a, &split_b) {
+                    (Some(a), Some(b)) => format!("{:+}", b.frames as i64 - a.frames as i64),
+                    _ => String::new(),
+                };
+                let mut row = vec![name];
+                row.extend(columns(&split_a, a));
+                row.extend(columns(&split_b, b));
+                row.push(delta);
+                row
+            })
+            .collect();
+
+        SplitTable {
+            headers: vec![
+                "Room".into(),
+                a_name.into(),
+                format!("{a_name} total"),
+                b_name.into(),
+                format!("{b_name} total"),
+                "Delta".into(),
+            ],
+            rows,
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let line = |cells: &[String]| {
+            let cells: Vec<_> = cells.iter().map(|cell| cell.replace('|', "\\|")).collect();
+            format!("| {} |\n", cells.join(" | "))
+        };
+
+        let mut s = line(&self.headers);
+        s.push_str(&line(&vec!["---".to_owned(); self.headers.len()]));
+        for row in &self.rows {
+            s.push_str(&line(row));
+        }
+        s
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut s = String::new();
+        for row in std::iter::once(&self.headers).chain(&self.rows) {
+            let cells: Vec<_> = row.iter().map(|cell| csv_field(cell)).collect();
+            let _ = writeln!(s, "{}", cells.join(","));
+        }
+        s
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_owned(),
+    }
+}
+
+pub fn frames_to_finaltime(frames: u32) -> String {
+    let ms = frames * 17;
+    let s = ms / 1000;
+    let min = s / 60;
+
+    format!("{}:{:0>2}.{:0>3}({frames})", min, s % 60, ms % 1000)
+}
+
+#[test]
+fn tables() {
+    let split = RoomSplit::new;
+    let a = [split("a-00", 0, 0, 100), split("a-01", 0, 100, 50)];
+    let b = [split("a-00", 0, 0, 90), split("a-02", 0, 90, 20)];
+
+    let table = SplitTable::side_by_side("#1", &a, "#2, new", &b);
+    assert_eq!(
+        table.rows[0],
+        ["a-00", "100", "0:01.700(100)", "90", "0:01.530(90)", "-10"]
+    );
+    assert_eq!(table.rows[2][0], "a-02");
+
+    let csv = table.to_csv();
+    assert!(csv.starts_with("Room,#1,#1 total,\"#2, new\",\"#2, new total\",Delta\n"));
+    assert_eq!(
+        SplitTable::single(&a).to_markdown(),
+        "| Room | Entry frame | Frames | Total |\n\
+         | --- | --- | --- | --- |\n\
+         | a-00 | 0 | 100 | 0:01.700(100) |\n\
+         | a-01 | 100 | 50 | 0:02.550(150) |\n"
+    );
+}
+
+#[test]
+fn splits_at_transitions() -> Result<()> {
+    let physics_inspector = PhysicsInspector {
+        recent_recordings: concat!(env!("CARGO_MANIFEST_DIR"), "/testdata").into(),
+    };
+    let splits = room_splits(&physics_inspector, 0)?;
+    let rooms: Vec<_> = splits
+        .iter()
+        .map(|split| (split.name(), split.entry_frame, split.frames))
+        .collect();
+    assert_eq!(
+        rooms,
+        [
+            ("a-00".to_owned(), 0, 4),
+            ("a-01".to_owned(), 4, 3),
+            ("a-00 (2)".to_owned(), 7, 2),
+        ]
+    );
+    assert_eq!(splits[2].cumulative(&splits), 9);
+    Ok(())
+}
@@ -0,0 +1,194 @@
+//! SVG export of annotated maps, with the rendered map embedded as a raster image and every
+//! recording as vector paths, mirroring the styling of `annotate_cct_recording_skia`.
+
+use std::fmt::Write;
+
+use annotate_celeste_map::{ColorMode, LineSettings};
+use anyhow::Result;
+use base64::Engine;
+use celesteloader::{cct_physics_inspector::PhysicsInspector, map::Bounds, map::Map};
+use indexmap::IndexMap;
+
+use crate::colors;
+
+/// Builds an SVG document of `background_png` with one group per recording,
+/// containing one group per room with the `<path>` segments inside it.
+pub fn annotated_map_svg(
+    background_png: &[u8],
+    bounds: Bounds,
+    map: &Map,
+    physics_inspector: &PhysicsInspector,
+    recordings: &[u32],
+    settings: LineSettings,
+) -> Result<String> {
+    let (width, height) = bounds.size;
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    )?;
+    if let ColorMode::Gradient = settings.color_mode {
+        write!(
+            svg,
+            r#"  <defs><linearGradient id="gradient" gradientUnits="userSpaceOnUse" x1="0" y1="0" x2="{width}" y2="{height}" spreadMethod="reflect">"#
+        )?;
+        for (offset, [r, g, b]) in colors::GRADIENT {
+            write!(
+                svg,
+                r#"<stop offset="{offset}" stop-color="rgb({r},{g},{b})"/>"#
+            )?;
+        }
+        writeln!(svg, "</linearGradient></defs>")?;
+    }
+    writeln!(
+        svg,
+        r#"  <image id="map" width="{width}" height="{height}" xlink:href="data:image/png;base64,{}"/>"#,
+        base64::engine::general_purpose::STANDARD.encode(background_png)
+    )?;
+
+    let shape_rendering = match settings.anti_alias {
+        true => "auto",
+        false => "crispEdges",
+    };
+
+    for (n, &recording) in recordings.iter().enumerate() {
+        let rooms = recording_segments(physics_inspector, recording, bounds, map, settings)?;
+
+        writeln!(
+            svg,
+            r#"  <g id="recording-{recording}" fill="none" stroke-width="{}" stroke-linecap="butt" stroke-linejoin="round" shape-rendering="{shape_rendering}">"#,
+            settings.width
+        )?;
+        for (room, segments) in rooms {
+            writeln!(svg, r#"    <g class="room" data-room="{}">"#, escape(&room))?;
+            for segment in segments {
+                let stroke = stroke(settings.color_mode, &segment.state, n);
+                writeln!(
+                    svg,
+                    r#"      <path class="{}" {stroke} d="{}"/>"#,
+                    escape(&segment.state),
+                    segment.data
+                )?;
+            }
+            writeln!(svg, "    </g>")?;
+        }
+        writeln!(svg, "  </g>")?;
+    }
+    writeln!(svg, "</svg>")?;
+
+    Ok(svg)
+}
+
+struct Segment {
+    state: String,
+    data: String,
+}
+
+/// Splits the path of a recording into segments by room, and by player state if the paths
+/// are colored by state. Rooms are ordered by when they were first entered.
+fn recording_segments(
+    physics_inspector: &PhysicsInspector,
+    recording: u32,
+    bounds: Bounds,
+    map: &Map,
+    settings: LineSettings,
+) -> Result<IndexMap<String, Vec<Segment>>> {
+    let mut points: Vec<(f32, f32, String)> = Vec::new();
+    for item in physics_inspector.position_log(recording)? {
+        let item = item?;
+        let state = item.flags.split(' ').next().unwrap_or_default().to_owned();
+
+        let new_entry = (item.x, item.y, state);
+        if points.last() != Some(&new_entry) {
+            points.push(new_entry);
+        }
+    }
+
+    let mut rooms: IndexMap<String, Vec<Segment>> = IndexMap::new();
+    if points.len() <= 1 {
+        return Ok(rooms);
+    }
+
+    let split_by_state = matches!(settings.color_mode, ColorMode::State);
+    let offset = (bounds.position.x as f32, bounds.position.y as f32);
+    let room_name = |x: f32, y: f32| {
+        map.room_at(x, y)
+            .map(|room| room.name.trim_start_matches("lvl_").to_owned())
+    };
+
+    let mut items = points.into_iter();
+    let (x, y, mut state) = items.next().unwrap();
+    let mut room = room_name(x, y).unwrap_or_default();
+    let mut data = format!("M{:.2} {:.2}", x - offset.0, y - offset.1);
+    let mut last = (x, y);
+
+    for (x, y, new_state) in items {
+        // transitions between rooms are drawn as part of the room that was entered
+        let new_room = room_name(x, y).unwrap_or_else(|| room.clone());
+        if new_room != room {
+            push_segment(&mut rooms, &room, state.clone(), std::mem::take(&mut data));
+            room = new_room;
+            data = format!("M{:.2} {:.2}", last.0 - offset.0, last.1 - offset.1);
+        }
+
+        let _ = write!(data, " L{:.2} {:.2}", x - offset.0, y - offset.1);
+        last = (x, y);
+
+        if split_by_state && new_state != state {
+            let new_data = format!("M{:.2} {:.2}", x - offset.0, y - offset.1);
+            push_segment(
+                &mut rooms,
+                &room,
+                state,
+                std::mem::replace(&mut data, new_data),
+            );
+        }
+        state = new_state;
+    }
+    push_segment(&mut rooms, &room, state, data);
+
+    Ok(rooms)
+}
+
+fn push_segment(
+    rooms: &mut IndexMap<String, Vec<Segment>>,
+    room: &str,
+    state: String,
+    data: String,
+) {
+    if data.contains('L') {
+        rooms
+            .entry(room.to_owned())
+            .or_default()
+            .push(Segment { state, data });
+    }
+}
+
+fn stroke(color_mode: ColorMode, state: &str, recording_index: usize) -> String {
+    match color_mode {
+        ColorMode::Gradient => r#"stroke="url(#gradient)""#.to_owned(),
+        ColorMode::State => {
+            let [r, g, b] = colors::state_color(state);
+            format!(r#"stroke="rgb({r},{g},{b})""#)
+        }
+        ColorMode::Random => {
+            let [r, g, b] = colors::random_color(recording_index);
+            format!(
+                r#"stroke="rgb({r},{g},{b})" stroke-opacity="{:.3}""#,
+                colors::RANDOM_ALPHA as f32 / 255.0
+            )
+        }
+        ColorMode::Color([r, g, b, a]) => format!(
+            r#"stroke="rgb({r},{g},{b})" stroke-opacity="{:.3}""#,
+            a as f32 / 255.0
+        ),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
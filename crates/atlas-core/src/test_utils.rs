@@ -0,0 +1,38 @@
+//! Fixtures shared by the tests.
+
+use anyhow::Result;
+use celesteloader::cct_physics_inspector::PhysicsInspector;
+use tempfile::TempDir;
+
+use crate::archive::{POSITION_LOG_SUFFIX, ROOM_LAYOUT_SUFFIX};
+
+/// Temp dir which is removed when dropped, also when an assertion failed.
+pub fn temp_dir() -> TempDir {
+    tempfile::Builder::new()
+        .prefix("atlas-test-")
+        .tempdir()
+        .expect("failed to create temp dir")
+}
+
+/// Writes a fake CCT recording of the Prologue, started `second`s into 2024 so that recordings
+/// get different keys.
+pub fn write_recording(
+    physics_inspector: &PhysicsInspector,
+    index: u32,
+    second: u32,
+    position_log: &str,
+) -> Result<()> {
+    let dir = &physics_inspector.recent_recordings;
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(
+        dir.join(format!("{index}{ROOM_LAYOUT_SUFFIX}")),
+        format!(
+            r#"{{"id":{index},"chapterName":"Prologue","sideName":"A-Side","frameCount":100,"recordingStarted":"2024-01-01T00:00:{second:02}+00:00","rooms":[]}}"#
+        ),
+    )?;
+    std::fs::write(
+        dir.join(format!("{index}{POSITION_LOG_SUFFIX}")),
+        position_log,
+    )?;
+    Ok(())
+}
@@ -0,0 +1,373 @@
+use std::path::PathBuf;
+
+use annotate_celeste_map::{ColorMode, LineSettings};
+use anyhow::{bail, Context, Result};
+use celestedebugrc::DebugRC;
+use celesteloader::cct_physics_inspector::PhysicsInspector;
+use celesteloader::CelesteInstallation;
+use celesterender::Layer;
+use clap::{Parser, Subcommand};
+
+use atlas_core::archive::Archive;
+use atlas_core::compare::{self, TimesaveFormat, TimesaveRecording, TimesaveSelection};
+use atlas_core::names::MapNames;
+use atlas_core::record::{self, FileStatus, RecordOptions};
+use atlas_core::render::{self, OutputFormat, RenderOptions, RenderRegion, RenderState};
+
+use crate::settings;
+
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// Path or name of the Celeste installation, defaults to the one active in the app
+    #[arg(long, global = true)]
+    pub celeste: Option<String>,
+    /// Language of map names, like `French`, defaults to the one picked in the app
+    #[arg(long, global = true)]
+    pub language: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Render CCT recordings onto a map without opening a window
+    Render(RenderArgs),
+    /// Run TAS files through DebugRC to create CCT recordings
+    Record(RecordArgs),
+    /// Compare the time CCT recordings of a map spent in each room
+    Compare(CompareArgs),
+}
+
+#[derive(clap::Args)]
+pub struct RenderArgs {
+    /// Map bin of the map to render, e.g. `Celeste/1-ForsakenCity`
+    #[arg(long)]
+    map_bin: String,
+    /// Comma separated CCT recording indices
+    #[arg(long, value_delimiter = ',', required = true)]
+    recordings: Vec<u32>,
+    /// Comma separated layers: fgtiles, bgtiles, entities, fgdecals, bgdecals, triggers or all
+    #[arg(long, value_delimiter = ',', default_value = "all", value_parser = parse_layer)]
+    layers: Vec<Layer>,
+    /// state, random, gradient or red
+    #[arg(long, default_value = "state", value_parser = parse_color_mode)]
+    color_mode: ColorMode,
+    #[arg(long, default_value_t = 2.0)]
+    width: f32,
+    #[arg(long)]
+    no_anti_alias: bool,
+    /// Read the recordings from the archive of pinned recordings
+    #[arg(long)]
+    archived: bool,
+    /// Render every room of the map instead of only the visited ones
+    #[arg(long)]
+    all_rooms: bool,
+    /// Draw every recording in its own color, with a legend and markers where paths diverge
+    #[arg(long)]
+    compare: bool,
+    /// Only render rooms like `a-03..a-07,b-00`, or an area like `x,y,width,height` in map pixels
+    #[arg(long, value_parser = parse_region)]
+    region: Option<RenderRegion>,
+    /// Pixels rendered around --region
+    #[arg(long, default_value_t = 16)]
+    padding: u32,
+    /// Label shown in the legend of --compare, e.g. `--label 3=old --label 4=new`.
+    /// Overrides the label set in the app
+    #[arg(long = "label", value_parser = parse_label)]
+    labels: Vec<(u32, String)>,
+    /// png, svg, gif, apng or frames, guessed from the extension of --out by default
+    #[arg(long, value_parser = parse_format)]
+    format: Option<OutputFormat>,
+    /// Game frames per frame of animated formats
+    #[arg(long, default_value_t = 3)]
+    frame_stride: u32,
+    /// Output file, or folder for `--format frames`
+    #[arg(long)]
+    out: PathBuf,
+}
+
+#[derive(clap::Args)]
+pub struct RecordArgs {
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+    /// Fastforward speed, ignored when recording with TASRecorder
+    #[arg(long, default_value_t = 500.0)]
+    speed: f32,
+    /// Run all files as one merged TAS
+    #[arg(long)]
+    merged: bool,
+    /// Only record the part of the TAS that changed since the last git commit
+    #[arg(long)]
+    only_changes: bool,
+    /// Also record the version from the last git commit
+    #[arg(long)]
+    git_tree: bool,
+    /// Record a video with TASRecorder
+    #[arg(long)]
+    tas_recorder: bool,
+}
+
+#[derive(clap::Args)]
+pub struct CompareArgs {
+    /// Map bin of the recorded map, e.g. `Celeste/1-ForsakenCity`
+    #[arg(long)]
+    map_bin: String,
+    /// Comma separated CCT recording indices, at least two
+    #[arg(long, value_delimiter = ',', required = true)]
+    recordings: Vec<u32>,
+    /// Recording the others are compared to, the slowest one by default
+    #[arg(long)]
+    baseline: Option<u32>,
+    /// Read the recordings from the archive of pinned recordings
+    #[arg(long)]
+    archived: bool,
+    /// text, markdown, csv or json, guessed from the extension of --out by default
+    #[arg(long, value_parser = parse_timesave_format)]
+    format: Option<TimesaveFormat>,
+    /// Output file, printed if unset
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+fn parse_layer(name: &str) -> Result<Layer, String> {
+    render::layer_from_name(name).ok_or_else(|| format!("unknown layer `{name}`"))
+}
+fn parse_format(name: &str) -> Result<OutputFormat, String> {
+    OutputFormat::from_name(name).ok_or_else(|| format!("unknown format `{name}`"))
+}
+fn parse_label(label: &str) -> Result<(u32, String), String> {
+    let (recording, label) = label
+        .split_once('=')
+        .ok_or_else(|| format!("expected `<recording>=<label>`, got `{label}`"))?;
+    let recording = recording
+        .parse()
+        .map_err(|_| format!("invalid recording index `{recording}`"))?;
+    Ok((recording, label.to_owned()))
+}
+fn parse_region(region: &str) -> Result<RenderRegion, String> {
+    RenderRegion::parse(region).map_err(|e| e.to_string())
+}
+fn parse_timesave_format(name: &str) -> Result<TimesaveFormat, String> {
+    TimesaveFormat::from_name(name).ok_or_else(|| format!("unknown format `{name}`"))
+}
+fn parse_color_mode(name: &str) -> Result<ColorMode, String> {
+    render::color_mode_from_name(name).ok_or_else(|| format!("unknown color mode `{name}`"))
+}
+
+pub fn run(command: Command, celeste: Option<String>, language: Option<String>) -> Result<()> {
+    let settings = settings::read_settings().unwrap_or_default();
+    let celeste_path = match celeste {
+        Some(celeste) => Some(
+            settings
+                .installations
+                .iter()
+                .find(|installation| installation.name == celeste)
+                .map_or_else(
+                    || PathBuf::from(celeste),
+                    |installation| installation.path.clone(),
+                ),
+        ),
+        None => settings
+            .active_installation()
+            .map(|installation| installation.path.clone()),
+    };
+    let celeste_path = celeste_path
+        .or_else(
+            || match atlas_core::detect::detect_installations().as_slice() {
+                [celeste] => Some(celeste.path.clone()),
+                _ => None,
+            },
+        )
+        .context("No Celeste installation configured, pass --celeste or pick one in the app")?;
+    let celeste = atlas_core::celeste_installation_from_path(celeste_path)?;
+    let mut map_names = MapNames::new(celeste.clone(), &language.unwrap_or(settings.language));
+
+    match command {
+        Command::Render(args) => {
+            let options = RenderOptions {
+                line_settings: LineSettings {
+                    width: args.width,
+                    anti_alias: !args.no_anti_alias,
+                    color_mode: args.color_mode,
+                },
+                layer: args
+                    .layers
+                    .into_iter()
+                    .fold(Layer::NONE, |acc, layer| acc | layer),
+                only_include_visited_rooms: !args.all_rooms,
+                compare: args.compare,
+                region: args.region.unwrap_or_default(),
+                region_padding: args.padding,
+            };
+
+            let physics_inspector = recordings_source(&celeste, args.archived)?;
+            let mut state = RenderState::new(&celeste)?;
+            state.set_recordings_source(physics_inspector.clone());
+            let mut labels = atlas_core::labels::labels_by_index(&physics_inspector)
+                .unwrap_or_else(|e| {
+                    eprintln!("Failed to read labels: {e:?}");
+                    Default::default()
+                });
+            labels.extend(args.labels);
+            let name = map_names
+                .map_name(&args.map_bin)
+                .unwrap_or_else(|| args.map_bin.clone());
+            let format = args.format.unwrap_or_else(|| {
+                args.out
+                    .extension()
+                    .and_then(|extension| OutputFormat::from_name(&extension.to_string_lossy()))
+                    .unwrap_or(OutputFormat::Png)
+            });
+            match format {
+                OutputFormat::Png => {
+                    let mut result = render::render_map(
+                        &mut state,
+                        &args.map_bin,
+                        &name,
+                        &args.recordings,
+                        &labels,
+                        options,
+                        &|status| println!("{status}"),
+                    )?;
+                    result
+                        .save_png(&args.out, celesterender::Compression::Balanced)
+                        .with_context(|| format!("failed to write {}", args.out.display()))?;
+                }
+                OutputFormat::Svg => {
+                    let svg = render::render_map_svg(
+                        &mut state,
+                        &args.map_bin,
+                        &name,
+                        &args.recordings,
+                        options,
+                        &|status| println!("{status}"),
+                    )?;
+                    std::fs::write(&args.out, svg)
+                        .with_context(|| format!("failed to write {}", args.out.display()))?;
+                }
+                OutputFormat::Gif | OutputFormat::Apng | OutputFormat::Frames => {
+                    atlas_core::animation::render_animation(
+                        &mut state,
+                        &args.map_bin,
+                        &name,
+                        &args.recordings,
+                        options,
+                        args.frame_stride,
+                        format,
+                        &args.out,
+                        &|status| println!("{status}"),
+                    )?;
+                }
+            }
+            println!("Saved {}", args.out.display());
+        }
+        Command::Record(args) => {
+            let debugrc = DebugRC::new();
+            let (ok, msg, _) = record::check_required_mods(&debugrc);
+            if !ok {
+                bail!("Could not connect to DebugRC. Is Celeste running?");
+            }
+            if let Some(msg) = msg {
+                eprintln!("{}", msg.trim_end());
+            }
+
+            let options = RecordOptions {
+                fastforward_speed: args.speed,
+                run_as_merged: args.merged,
+                record_git_tree: args.git_tree,
+                only_record_changes: args.only_changes,
+                enable_tas_recorder: args.tas_recorder,
+            };
+            let mut failed = 0;
+            record::record_queue(
+                &args.files,
+                &options,
+                &celeste,
+                &debugrc,
+                &record::CancellationToken::new(),
+                |i, status| match status {
+                    FileStatus::Succeeded => println!("Recorded {}", args.files[i].display()),
+                    FileStatus::Failed(e) => {
+                        failed += 1;
+                        eprintln!("Failed to record {}: {e}", args.files[i].display());
+                    }
+                    FileStatus::Pending | FileStatus::Running => {}
+                },
+                |progress, msg| {
+                    println!("[{:>3.0}%] {msg}", progress * 100.0);
+                },
+            )?;
+            if failed > 0 {
+                bail!("{failed} of {} files failed to record", args.files.len());
+            }
+            println!("Done!");
+        }
+        Command::Compare(args) => {
+            let physics_inspector = recordings_source(&celeste, args.archived)?;
+            let labels =
+                atlas_core::labels::labels_by_index(&physics_inspector).unwrap_or_else(|e| {
+                    eprintln!("Failed to read labels: {e:?}");
+                    Default::default()
+                });
+            let baseline = match args.baseline {
+                Some(baseline) => Some(
+                    args.recordings
+                        .iter()
+                        .position(|&i| i == baseline)
+                        .context("--baseline has to be one of --recordings")?,
+                ),
+                None => None,
+            };
+
+            let selection = TimesaveSelection {
+                map_name: map_names
+                    .map_name(&args.map_bin)
+                    .unwrap_or_else(|| args.map_bin.clone()),
+                map_bin: args.map_bin,
+                recordings: args
+                    .recordings
+                    .iter()
+                    .map(|&index| TimesaveRecording {
+                        physics_inspector: physics_inspector.clone(),
+                        index,
+                        name: match labels.get(&index) {
+                            Some(label) => format!("#{index} {label}"),
+                            None => format!("#{index}"),
+                        },
+                    })
+                    .collect(),
+                baseline,
+            };
+            let comparisons = compare::compare_timesave(&[selection])?;
+
+            let format = args.format.unwrap_or_else(|| {
+                args.out
+                    .as_ref()
+                    .and_then(|out| out.extension())
+                    .and_then(|extension| TimesaveFormat::from_name(&extension.to_string_lossy()))
+                    .unwrap_or(TimesaveFormat::Text)
+            });
+            let output = compare::export_timesave(&comparisons, format)?;
+            match args.out {
+                Some(out) => {
+                    std::fs::write(&out, output)
+                        .with_context(|| format!("failed to write {}", out.display()))?;
+                    println!("Saved {}", out.display());
+                }
+                None => print!("{output}"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Where the recordings are read from, CCT's recent recordings or the archive.
+fn recordings_source(celeste: &CelesteInstallation, archived: bool) -> Result<PhysicsInspector> {
+    match archived {
+        true => Ok(Archive::open_default()?.physics_inspector().clone()),
+        false => Ok(PhysicsInspector::new(celeste)),
+    }
+}
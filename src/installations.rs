@@ -0,0 +1,236 @@
+use std::cell::{RefCell, RefMut};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
+use atlas_core::archive::Archive;
+use atlas_core::detect;
+use atlas_core::names::MapNames;
+use celesteloader::cct_physics_inspector::PhysicsInspector;
+use celesteloader::CelesteInstallation;
+use notify_debouncer_full::{notify::RecommendedWatcher, Debouncer, RecommendedCache};
+use slint::{ComponentHandle, ModelRc, SharedString, VecModel, Weak};
+
+use crate::settings::{self, Settings};
+use crate::{recordings, Installations, MainWindow, PickInstallationWindow};
+
+/// The Celeste installation currently selected in the UI, shared between all callbacks
+#[derive(Clone)]
+pub struct ActiveInstallation {
+    celeste: Rc<RefCell<CelesteInstallation>>,
+    /// Map names of the installation in the language picked in the recordings list, kept
+    /// across reloads as finding the mod of a map opens every mod zip
+    map_names: Rc<RefCell<MapNames>>,
+    /// Archive of pinned recordings, shared between installations, or why it couldn't be opened
+    archive: Rc<Result<Archive, String>>,
+}
+
+impl ActiveInstallation {
+    pub fn new(celeste: CelesteInstallation, language: &str) -> Self {
+        let archive = Archive::open_default().map_err(|e| {
+            eprintln!("Failed to open archive: {e:?}");
+            format!("{e:#}")
+        });
+        ActiveInstallation {
+            map_names: Rc::new(RefCell::new(MapNames::new(celeste.clone(), language))),
+            celeste: Rc::new(RefCell::new(celeste)),
+            archive: Rc::new(archive),
+        }
+    }
+
+    pub fn get(&self) -> CelesteInstallation {
+        self.celeste.borrow().clone()
+    }
+
+    pub fn physics_inspector(&self) -> PhysicsInspector {
+        self.celeste.borrow().physics_inspector()
+    }
+
+    pub fn archive(&self) -> Result<&Archive> {
+        self.archive.as_ref().as_ref().map_err(|e| anyhow!("{e}"))
+    }
+
+    pub fn map_names(&self) -> RefMut<'_, MapNames> {
+        self.map_names.borrow_mut()
+    }
+
+    /// Forgets the map names found so far and looks them up in `language` from now on.
+    pub fn set_language(&self, language: &str) {
+        *self.map_names.borrow_mut() = MapNames::new(self.get(), language);
+    }
+
+    fn set(&self, celeste: CelesteInstallation) {
+        let language = self.map_names.borrow().language().to_owned();
+        *self.map_names.borrow_mut() = MapNames::new(celeste.clone(), &language);
+        *self.celeste.borrow_mut() = celeste;
+    }
+}
+
+pub fn setup(
+    installations_global: Installations<'_>,
+    main_window: Weak<MainWindow>,
+    active: ActiveInstallation,
+    settings: &Settings,
+) {
+    let watcher = Rc::new(RefCell::new(start_watcher(
+        &active.physics_inspector(),
+        &main_window,
+    )));
+
+    update_installations_ui(&installations_global, settings);
+
+    installations_global.on_switch_installation({
+        let handle = main_window.clone();
+        let active = active.clone();
+        let watcher = watcher.clone();
+        move |i| {
+            let handle = handle.unwrap();
+            let result = (|| -> Result<_> {
+                let mut settings = settings::read_settings()?;
+                let installation = settings
+                    .installations
+                    .get(i as usize)
+                    .ok_or_else(|| anyhow!("unknown installation"))?;
+                let celeste =
+                    atlas_core::celeste_installation_from_path(installation.path.clone())?;
+
+                settings.active_installation = i as usize;
+                settings::write_settings(&settings)?;
+
+                Ok((celeste, settings))
+            })();
+
+            match result {
+                Ok((celeste, settings)) => {
+                    switch_to(&handle, &active, &watcher, celeste);
+                    update_installations_ui(&handle.global::<Installations>(), &settings);
+                }
+                Err(e) => {
+                    handle.set_error(format!("Could not switch installation: {e:?}").into());
+                    if let Ok(settings) = settings::read_settings() {
+                        update_installations_ui(&handle.global::<Installations>(), &settings);
+                    }
+                }
+            }
+        }
+    });
+    installations_global.on_add_installation({
+        let handle = main_window.clone();
+        move || {
+            let handle = handle.unwrap();
+
+            let Some(path) = rfd::FileDialog::new()
+                .set_title("Pick Celeste Folder")
+                .pick_folder()
+            else {
+                return;
+            };
+
+            let result = (|| -> Result<_> {
+                let celeste = atlas_core::celeste_installation_from_path(path)?;
+                let mut settings = settings::read_settings()?;
+                settings.activate_installation_path(celeste.path.clone());
+                settings::write_settings(&settings)?;
+                Ok((celeste, settings))
+            })();
+
+            match result {
+                Ok((celeste, settings)) => {
+                    switch_to(&handle, &active, &watcher, celeste);
+                    update_installations_ui(&handle.global::<Installations>(), &settings);
+                }
+                Err(e) => handle.set_error(format!("{e:?}").into()),
+            }
+        }
+    });
+}
+
+fn update_installations_ui(installations_global: &Installations<'_>, settings: &Settings) {
+    let names: Vec<SharedString> = settings
+        .installations
+        .iter()
+        .map(|installation| installation.name.as_str().into())
+        .collect();
+    installations_global.set_names(ModelRc::new(VecModel::from(names)));
+    installations_global.set_active(settings.active_installation as i32);
+}
+
+/// Replaces the active installation and restarts everything that depends on it.
+fn switch_to(
+    handle: &MainWindow,
+    active: &ActiveInstallation,
+    watcher: &RefCell<Option<Debouncer<RecommendedWatcher, RecommendedCache>>>,
+    celeste: CelesteInstallation,
+) {
+    active.set(celeste);
+
+    let physics_inspector = active.physics_inspector();
+    watcher.replace(None);
+    watcher.replace(start_watcher(&physics_inspector, &handle.as_weak()));
+
+    handle.set_error("".into());
+    handle.set_status("".into());
+    recordings::set_languages(handle, &active.get());
+    recordings::read_recordings_update_main(handle.clone_strong(), active);
+}
+
+fn start_watcher(
+    physics_inspector: &PhysicsInspector,
+    main_window: &Weak<MainWindow>,
+) -> Option<Debouncer<RecommendedWatcher, RecommendedCache>> {
+    match recordings::watcher::start_watcher(physics_inspector, main_window.clone()) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            main_window
+                .unwrap()
+                .set_error(format!("Cannot listen to CCT changes in the background: {e:?}").into());
+            None
+        }
+    }
+}
+
+/// Looks for Celeste installations and lets the user choose if there are several.
+///
+/// Returns an error if none were found or the user wants to pick a folder manually.
+pub fn pick_detected_installation() -> Result<CelesteInstallation> {
+    let mut installations = detect::detect_installations();
+    match installations.len() {
+        0 => Err(anyhow!("could not find celeste")),
+        1 => Ok(installations.remove(0)),
+        _ => {
+            let paths: Vec<SharedString> = installations
+                .iter()
+                .map(|celeste| celeste.path.to_string_lossy().as_ref().into())
+                .collect();
+
+            let window = PickInstallationWindow::new()?;
+            window.set_installations(ModelRc::new(VecModel::from(paths)));
+
+            let picked = Rc::new(RefCell::new(None));
+            window.on_picked({
+                let picked = picked.clone();
+                let handle = window.as_weak();
+                move |path| {
+                    *picked.borrow_mut() = Some(PathBuf::from(path.as_str()));
+                    let _ = handle.unwrap().hide();
+                }
+            });
+            window.on_browse({
+                let handle = window.as_weak();
+                move || {
+                    let _ = handle.unwrap().hide();
+                }
+            });
+            window.run()?;
+
+            let picked = picked.borrow_mut().take();
+            picked
+                .and_then(|path| {
+                    installations
+                        .into_iter()
+                        .find(|celeste| celeste.path == path)
+                })
+                .ok_or_else(|| anyhow!("no installation picked"))
+        }
+    }
+}
@@ -1,11 +1,14 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::{path::PathBuf, time::Duration};
+use std::time::Duration;
 
-use anyhow::{bail, Result};
-use celesteloader::{cct_physics_inspector::PhysicsInspector, CelesteInstallation};
+use atlas_core::celeste_installation_from_path;
+use clap::Parser;
+use installations::ActiveInstallation;
 use slint::{ComponentHandle, ModelRc};
 
+mod cli;
+mod installations;
 mod record_tas;
 mod recordings;
 mod render;
@@ -13,26 +16,27 @@ mod settings;
 
 slint::include_modules!();
 
-fn celeste_installation_from_path(path: PathBuf) -> Result<CelesteInstallation> {
-    if !path.join("Celeste").exists() && !path.join("Celeste.exe").exists() {
-        bail!("Directory does not contain Celeste.exe or Celeste file");
+pub fn main() {
+    let args = cli::Args::parse();
+    if let Some(command) = args.command {
+        if let Err(e) = cli::run(command, args.celeste, args.language) {
+            eprintln!("{e:?}");
+            std::process::exit(1);
+        }
+        return;
     }
-    Ok(CelesteInstallation { path })
-}
 
-pub fn main() {
     let mut settings = settings::read_settings().unwrap_or_default();
-    let mut celeste = match settings.celeste_path {
-        Some(path) => celeste_installation_from_path(path),
-        // None => CelesteInstallation::detect(),
-        None => Err(anyhow::anyhow!("could not find celeste")),
+    let mut celeste = match settings.active_installation() {
+        Some(installation) => celeste_installation_from_path(installation.path.clone()),
+        None => installations::pick_detected_installation(),
     };
 
     let celeste = loop {
         let error = match celeste {
             Ok(celeste) => {
-                settings.celeste_path = Some(celeste.path.clone());
-                let _ = settings::write_settings(settings);
+                settings.activate_installation_path(celeste.path.clone());
+                let _ = settings::write_settings(&settings);
                 break celeste;
             }
             Err(error) => error,
@@ -42,33 +46,28 @@ pub fn main() {
             .pick_folder();
         let Some(path) = files else { return };
 
-        celeste = celeste_installation_from_path(PathBuf::from(path.to_owned()));
+        celeste = celeste_installation_from_path(path.to_owned());
     };
 
-    let physics_inspector = PhysicsInspector::new(&celeste);
+    let active = ActiveInstallation::new(celeste, &settings.language);
 
     let main_window = MainWindow::new().unwrap();
 
     let (recordings_unfiltered, filter_model) =
-        recordings::load_model(&main_window, &physics_inspector);
-
-    let _watcher =
-        match recordings::watcher::start_watcher(&physics_inspector, main_window.as_weak()) {
-            Ok(watcher) => Some(watcher),
-            Err(e) => {
-                main_window.set_error(
-                    format!("Cannot listen to CCT changes in the background: {e:?}").into(),
-                );
-                None
-            }
-        };
+        recordings::load_model(&main_window, &active, &settings);
+
+    installations::setup(
+        main_window.global::<Installations>(),
+        main_window.as_weak(),
+        active.clone(),
+        &settings,
+    );
 
     recordings::setup(
         main_window.global::<Recordings>(),
         main_window.as_weak(),
         recordings_unfiltered,
-        &filter_model,
-        &celeste,
+        active.clone(),
     );
 
     let record_tas_global = main_window.global::<RecordTAS>();
@@ -89,7 +88,7 @@ pub fn main() {
 
                 for i in 0..total_attempts {
                     let (ok, msg, tas_recorder_installed) =
-                        record_tas::check_required_mods(&celestedebugrc::DebugRC::new());
+                        atlas_core::record::check_required_mods(&celestedebugrc::DebugRC::new());
 
                     let time = i * attempts_interval;
 
@@ -123,16 +122,28 @@ pub fn main() {
         main_window.global::<Render>(),
         main_window.as_weak(),
         &filter_model,
-        celeste.clone(),
+        active.clone(),
+        &settings,
     );
 
     record_tas::setup(
         main_window.global::<RecordTAS>(),
         main_window.as_weak(),
-        celeste,
-        physics_inspector,
+        active,
+        &settings.record,
     );
 
     main_window.set_recordings(ModelRc::from(filter_model));
     main_window.run().unwrap();
+
+    let render_settings =
+        render::render_settings_from_ui(&main_window.global::<Render>().get_render_settings());
+    let record_settings =
+        record_tas::record_settings_from_ui(&main_window.global::<RecordTAS>().get_settings());
+    if let Err(e) = settings::update_settings(|settings| {
+        settings.render = render_settings;
+        settings.record = record_settings;
+    }) {
+        eprintln!("Failed to save settings: {e:?}");
+    }
 }
@@ -0,0 +1,179 @@
+//! Sorting and grouping of the recordings list. Both work on the [`Recording`]s of the UI model,
+//! so rearranging the list keeps which recordings are checked.
+
+use std::rc::Rc;
+
+use indexmap::IndexMap;
+use slint::VecModel;
+
+use super::filtered_recordings::filter_recordings;
+use super::query::Query;
+use crate::{Recording, RecordingGroup};
+
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum SortBy {
+    /// Recent recordings in CCT's order, followed by the archived ones
+    #[default]
+    Index,
+    Newest,
+    FrameCount,
+    Chapter,
+    StartRoom,
+}
+
+impl SortBy {
+    pub fn from_name(name: &str) -> Option<SortBy> {
+        Some(match name {
+            "Index" => SortBy::Index,
+            "Newest" => SortBy::Newest,
+            "Frames" => SortBy::FrameCount,
+            "Chapter" => SortBy::Chapter,
+            "Start room" => SortBy::StartRoom,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum GroupBy {
+    #[default]
+    Map,
+    /// The first segment of the map bin, like `Celeste` or the folder of a mod
+    Campaign,
+    Day,
+    None,
+}
+
+impl GroupBy {
+    pub fn from_name(name: &str) -> Option<GroupBy> {
+        Some(match name {
+            "Map" => GroupBy::Map,
+            "Campaign" => GroupBy::Campaign,
+            "Day" => GroupBy::Day,
+            "None" => GroupBy::None,
+            _ => return None,
+        })
+    }
+}
+
+/// Sorts the recordings and groups them, groups are ordered by their first recording.
+pub fn arrange(
+    mut recordings: Vec<Recording>,
+    sort_by: SortBy,
+    group_by: GroupBy,
+    query: &Rc<Query>,
+) -> Vec<RecordingGroup> {
+    // all sorts are stable, so ties stay in index order
+    recordings.sort_by_key(|recording| (recording.archived, recording.i));
+    match sort_by {
+        SortBy::Index => {}
+        SortBy::Newest => recordings.sort_by(|a, b| b.started.cmp(&a.started)),
+        SortBy::FrameCount => recordings.sort_by_key(|recording| recording.frame_count),
+        SortBy::Chapter => recordings
+            .sort_by(|a, b| (&a.chapter_name, &a.map_bin).cmp(&(&b.chapter_name, &b.map_bin))),
+        SortBy::StartRoom => recordings.sort_by(|a, b| a.start_room.cmp(&b.start_room)),
+    }
+
+    let mut groups = IndexMap::<String, (String, Vec<Recording>)>::new();
+    for recording in recordings {
+        let (id, title) = group_of(&recording, group_by);
+        groups
+            .entry(id)
+            .or_insert_with(|| (title, Vec::new()))
+            .1
+            .push(recording);
+    }
+
+    groups
+        .into_iter()
+        .map(|(id, (title, recordings))| RecordingGroup {
+            id: id.into(),
+            title: title.into(),
+            selectable: recordings
+                .iter()
+                .any(|recording| !recording.map_bin.is_empty()),
+            checked: recordings.iter().any(|recording| recording.checked),
+            recordings: filter_recordings(Rc::new(VecModel::from(recordings)), query.clone()),
+        })
+        .collect()
+}
+
+/// Id and title of the group the recording belongs to.
+fn group_of(recording: &Recording, group_by: GroupBy) -> (String, String) {
+    let archived = match recording.archived {
+        true => "[archived] ",
+        false => "",
+    };
+    match group_by {
+        GroupBy::Map => {
+            let old_cct = match recording.map_bin.is_empty() {
+                true => "[old CCT] ",
+                false => "",
+            };
+            (
+                format!(
+                    "{}/{}/{}",
+                    recording.archived, recording.map_bin, recording.chapter_name
+                ),
+                format!("{old_cct}{archived}{}", recording.chapter_name),
+            )
+        }
+        GroupBy::Campaign => {
+            let campaign = recording
+                .map_bin
+                .split('/')
+                .next()
+                .filter(|campaign| !campaign.is_empty())
+                .unwrap_or("[old CCT]");
+            (
+                format!("{}/{campaign}", recording.archived),
+                format!("{archived}{campaign}"),
+            )
+        }
+        GroupBy::Day => match recording.date.as_str() {
+            "" => (String::new(), "Unknown date".into()),
+            date => (date.to_owned(), date.to_owned()),
+        },
+        GroupBy::None => (String::new(), "All recordings".into()),
+    }
+}
+
+#[test]
+fn arrange_by_day() {
+    use slint::Model;
+
+    let recording = |i: i32, date: &str, frame_count: i32, checked: bool| Recording {
+        i,
+        map_bin: "Celeste/1-ForsakenCity".into(),
+        date: date.into(),
+        frame_count,
+        checked,
+        ..Default::default()
+    };
+    let recordings = vec![
+        recording(0, "2024-01-02", 300, false),
+        recording(1, "2024-01-01", 100, true),
+        recording(2, "2024-01-02", 200, false),
+    ];
+
+    let groups = arrange(
+        recordings,
+        SortBy::FrameCount,
+        GroupBy::Day,
+        &Rc::new(Query::default()),
+    );
+    let groups: Vec<_> = groups
+        .iter()
+        .map(|group| {
+            let indices: Vec<_> = group.recordings.iter().map(|rec| rec.i).collect();
+            (group.title.to_string(), group.checked, indices)
+        })
+        .collect();
+    assert_eq!(
+        groups,
+        [
+            ("2024-01-01".to_owned(), true, vec![1]),
+            ("2024-01-02".to_owned(), false, vec![2, 0]),
+        ]
+    );
+}
@@ -1,35 +1,67 @@
-use std::sync::{LazyLock, RwLock};
 use std::{any::Any, rc::Rc};
 
-use crate::MapRecordings;
+use crate::recordings::query::Query;
+use crate::{Recording, RecordingGroup};
 use slint::{FilterModel, Model, ModelRc, VecModel};
 
-static FILTER: LazyLock<RwLock<String>> = LazyLock::new(Default::default);
+type InnerModel = Rc<VecModel<RecordingGroup>>;
 
-type InnerModel = Rc<VecModel<MapRecordings>>;
+type RecordingFilter = Box<dyn Fn(&Recording) -> bool>;
+type RecordingsModel = FilterModel<Rc<VecModel<Recording>>, RecordingFilter>;
 
-fn filter_function(rec: &MapRecordings) -> bool {
-    let filter = FILTER.read().unwrap();
-
-    rec.chapter_name.to_lowercase().contains(filter.as_str())
-        || rec.map_bin.to_lowercase().contains(filter.as_str())
+/// The recordings of each group are filtered by themselves, groups without any matches are hidden.
+fn filter_function(group: &RecordingGroup) -> bool {
+    group.recordings.row_count() > 0
 }
 
 pub fn create_model(
     m: InnerModel,
-) -> FilterModel<InnerModel, impl Fn(&MapRecordings) -> bool + 'static> {
+) -> FilterModel<InnerModel, impl Fn(&RecordingGroup) -> bool + 'static> {
     FilterModel::new(m, filter_function)
 }
 
-pub fn set_filter(
-    filter: &str,
-    filter_model: &FilterModel<InnerModel, impl Fn(&MapRecordings) -> bool + 'static>,
-) {
-    *FILTER.write().unwrap() = filter.to_lowercase();
-    filter_model.reset();
+/// Wraps `recordings` in a model which only shows the ones matching `query`.
+pub fn filter_recordings(
+    recordings: Rc<VecModel<Recording>>,
+    query: Rc<Query>,
+) -> ModelRc<Recording> {
+    let filter: RecordingFilter = Box::new(move |recording| query.matches(recording));
+    ModelRc::new(FilterModel::new(recordings, filter))
+}
+
+pub fn set_filter(filter: &str, groups: &VecModel<RecordingGroup>) {
+    let query = Rc::new(Query::parse(filter));
+    for i in 0..groups.row_count() {
+        let mut group = groups.row_data(i).unwrap();
+        let recordings = get_source_recordings(&group.recordings);
+        group.recordings = filter_recordings(recordings, query.clone());
+        groups.set_row_data(i, group);
+    }
+}
+
+/// All recordings of a group, including the ones hidden by the filter.
+pub(super) fn get_source_recordings(model: &ModelRc<Recording>) -> Rc<VecModel<Recording>> {
+    model
+        .as_any()
+        .downcast_ref::<RecordingsModel>()
+        .unwrap()
+        .source_model()
+        .clone()
+}
+
+/// All recordings in the list, including the ones hidden by the filter.
+pub fn all_recordings(groups: &VecModel<RecordingGroup>) -> Vec<Recording> {
+    groups
+        .iter()
+        .flat_map(|group| {
+            get_source_recordings(&group.recordings)
+                .iter()
+                .collect::<Vec<_>>()
+        })
+        .collect()
 }
 
-pub fn get_source_vec_model(model: &ModelRc<MapRecordings>) -> &VecModel<MapRecordings> {
+pub fn get_source_vec_model(model: &ModelRc<RecordingGroup>) -> &VecModel<RecordingGroup> {
     fn name_fn_helper<M, F>(_: F, any: &dyn Any) -> Option<&FilterModel<M, F>>
     where
         M: Model + 'static,
@@ -42,6 +74,6 @@ pub fn get_source_vec_model(model: &ModelRc<MapRecordings>) -> &VecModel<MapReco
     filter_model
         .source_model()
         .as_any()
-        .downcast_ref::<VecModel<MapRecordings>>()
+        .downcast_ref::<VecModel<RecordingGroup>>()
         .unwrap()
 }
@@ -1,25 +1,49 @@
 use anyhow::Result;
-use celesteloader::{cct_physics_inspector::PhysicsInspector, CelesteInstallation};
-use chrono::DateTime;
+use atlas_core::bundle;
+use atlas_core::compare::{
+    self, MapTimesave, TimesaveFormat, TimesaveRecording, TimesaveSelection,
+};
+use atlas_core::labels::{self, Label};
+use atlas_core::names::{self, MapNames};
+use atlas_core::recordings::MapGroup;
+use celesteloader::cct_physics_inspector::PhysicsInspector;
+use celesteloader::CelesteInstallation;
 use copypasta::ClipboardProvider;
 use indexmap::IndexMap;
-use slint::{ComponentHandle, FilterModel, Model, VecModel, Weak};
+use slint::{ComponentHandle, FilterModel, Model, ModelRc, SharedString, VecModel, Weak};
+use std::collections::HashSet;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
+mod arrange;
 mod filtered_recordings;
+mod query;
+mod split_table;
+mod sync;
 pub mod watcher;
 
-use crate::{MainWindow, MapRecordings, Recording, Recordings};
+use crate::installations::ActiveInstallation;
+use crate::settings::{self, Settings};
+use crate::{MainWindow, Recording, RecordingGroup, Recordings};
+use arrange::{GroupBy, SortBy};
+use query::Query;
 
 pub fn load_model(
     main_window: &MainWindow,
-    physics_inspector: &PhysicsInspector,
+    active: &ActiveInstallation,
+    settings: &Settings,
 ) -> (
-    Rc<VecModel<MapRecordings>>,
-    Rc<FilterModel<Rc<VecModel<MapRecordings>>, impl Fn(&MapRecordings) -> bool>>,
+    Rc<VecModel<RecordingGroup>>,
+    Rc<FilterModel<Rc<VecModel<RecordingGroup>>, impl Fn(&RecordingGroup) -> bool>>,
 ) {
-    let result = match read_recordings(physics_inspector) {
-        Ok(recordings) => recordings,
+    let recordings_global = main_window.global::<Recordings>();
+    recordings_global.set_sort_by(settings.recordings_sort_by.as_str().into());
+    recordings_global.set_group_by(settings.recordings_group_by.as_str().into());
+    recordings_global.set_language(settings.language.as_str().into());
+    set_languages(main_window, &active.get());
+
+    let result = match read_recordings(active) {
+        Ok(recordings) => arrange_recordings(main_window, recordings),
         Err(e) => {
             main_window.set_error(format!("{e:?}").into());
             Vec::new()
@@ -37,9 +61,8 @@ pub fn load_model(
 pub fn setup(
     recordings_global: Recordings<'_>,
     main_window: Weak<MainWindow>,
-    recordings_unfiltered: Rc<VecModel<MapRecordings>>,
-    filter_model: &Rc<FilterModel<Rc<VecModel<MapRecordings>>, impl Fn(&MapRecordings) -> bool>>,
-    celeste: &CelesteInstallation,
+    recordings_unfiltered: Rc<VecModel<RecordingGroup>>,
+    active: ActiveInstallation,
 ) {
     recordings_global.on_select_all({
         let handle = main_window.clone();
@@ -47,283 +70,546 @@ pub fn setup(
         move || {
             let all_selected = recordings
                 .iter()
-                .all(|map| map.checked || map.map_bin.is_empty());
+                .all(|group| group.checked || !group.selectable);
             let new_selection = !all_selected;
 
             let mut new = Vec::new();
             for j in 0..recordings.row_count() {
-                let mut map = recordings.row_data(j).unwrap();
-                let has_map_bin = !map.map_bin.is_empty();
+                let mut group = recordings.row_data(j).unwrap();
 
-                for i in 0..map.recordings.row_count() {
-                    let mut recording = map.recordings.row_data(i).unwrap();
-                    recording.checked = new_selection && has_map_bin;
-                    map.recordings.set_row_data(i, recording);
+                for i in 0..group.recordings.row_count() {
+                    let mut recording = group.recordings.row_data(i).unwrap();
+                    recording.checked = new_selection && !recording.map_bin.is_empty();
+                    group.recordings.set_row_data(i, recording);
                 }
 
-                map.checked = new_selection && has_map_bin;
-                new.push(map);
+                group.checked = new_selection && group.selectable;
+                new.push(group);
             }
             recordings.set_vec(new);
 
-            recalc_compare_recordings_enabled(handle.clone(), &recordings);
+            recalc_selection_enabled(&handle.unwrap(), &recordings);
         }
     });
-    recordings_global.on_toggle_map({
+    recordings_global.on_toggle_group({
         let handle = main_window.clone();
         let recordings = recordings_unfiltered.clone();
-        move |map_bin| {
-            let Some(list) = recordings
-                .iter()
-                .find(|recording| recording.map_bin == map_bin)
-            else {
+        move |id| {
+            let Some(group) = recordings.iter().find(|group| group.id == id) else {
                 return;
             };
 
-            for i in 0..list.recordings.row_count() {
-                let mut recording = list.recordings.row_data(i).unwrap();
-                recording.checked = list.checked;
-                list.recordings.set_row_data(i, recording);
+            for i in 0..group.recordings.row_count() {
+                let mut recording = group.recordings.row_data(i).unwrap();
+                recording.checked = group.checked && !recording.map_bin.is_empty();
+                group.recordings.set_row_data(i, recording);
             }
 
-            recalc_compare_recordings_enabled(handle.clone(), &recordings);
+            recalc_selection_enabled(&handle.unwrap(), &recordings);
         }
     });
-    recordings_global.on_toggle_map_recording({
+    recordings_global.on_toggle_group_recording({
         let handle = main_window.clone();
         let recordings = recordings_unfiltered.clone();
-        move |map_bin, _| {
-            let Some((j, mut list)) = recordings
+        move |id| {
+            let Some((j, mut group)) = recordings
                 .iter()
                 .enumerate()
-                .find(|(_, recording)| recording.map_bin == map_bin)
+                .find(|(_, group)| group.id == id)
             else {
                 return;
             };
 
-            let any_checked = list.recordings.iter().any(|rec| rec.checked);
-            list.checked = any_checked;
-            recordings.set_row_data(j, list);
+            let any_checked = group.recordings.iter().any(|rec| rec.checked);
+            group.checked = any_checked;
+            recordings.set_row_data(j, group);
 
-            recalc_compare_recordings_enabled(handle.clone(), &recordings);
+            recalc_selection_enabled(&handle.unwrap(), &recordings);
         }
     });
 
+    let comparisons = Arc::new(Mutex::new(Vec::new()));
     recordings_global.on_compare_times({
         let recordings = recordings_unfiltered.clone();
-        let celeste = celeste.clone();
+        let active = active.clone();
         let handle = main_window.clone();
+        let comparisons = comparisons.clone();
         move || {
-            compare_recordings(handle.clone(), &recordings, &celeste);
+            compare_recordings(handle.clone(), &recordings, &active, comparisons.clone());
         }
     });
+    recordings_global.on_export_timesave({
+        let handle = main_window.clone();
+        move || show_timesave(&handle.unwrap(), &comparisons.lock().unwrap())
+    });
     recordings_global.on_refresh_recordings({
         let recordings = recordings_unfiltered.clone();
-        let physics_inspector = celeste.physics_inspector();
+        let active = active.clone();
+        let handle = main_window.clone();
+        move || {
+            let handle = handle.unwrap();
+            reload_recordings(&handle, &recordings, &active);
+        }
+    });
+    recordings_global.on_set_language({
+        let recordings = recordings_unfiltered.clone();
+        let active = active.clone();
+        let handle = main_window.clone();
+        move |language| {
+            let handle = handle.unwrap();
+            handle.global::<Recordings>().set_language(language.clone());
+            active.set_language(&language);
+            if let Err(e) =
+                settings::update_settings(|settings| settings.language = language.into())
+            {
+                handle.set_error(format!("Failed to save settings: {e:?}").into());
+            }
+            reload_recordings(&handle, &recordings, &active);
+        }
+    });
+    recordings_global.on_arrange({
+        let recordings = recordings_unfiltered.clone();
+        let handle = main_window.clone();
+        move || {
+            let handle = handle.unwrap();
+            let recordings_global = handle.global::<Recordings>();
+            let result = settings::update_settings(|settings| {
+                settings.recordings_sort_by = recordings_global.get_sort_by().into();
+                settings.recordings_group_by = recordings_global.get_group_by().into();
+            });
+            if let Err(e) = result {
+                handle.set_error(format!("Failed to save settings: {e:?}").into());
+            }
+
+            let all = filtered_recordings::all_recordings(&recordings);
+            sync::sync_groups(&recordings, arrange_recordings(&handle, all));
+        }
+    });
+    recordings_global.on_set_label({
+        let recordings = recordings_unfiltered.clone();
+        let active = active.clone();
+        let handle = main_window.clone();
+        move |key, label, note| {
+            let label = Label {
+                label: label.trim().to_owned(),
+                note: note.trim().to_owned(),
+            };
+            let handle = handle.unwrap();
+            if let Err(e) = labels::set_label(&active.physics_inspector(), &key, label.clone()) {
+                handle.set_error(format!("{e:?}").into());
+                return;
+            }
+            // archived copies share the key and get the same label
+            let archived = active.archive().and_then(|archive| {
+                labels::set_label(archive.physics_inspector(), &key, label.clone())
+            });
+            if let Err(e) = archived {
+                handle.set_error(format!("Failed to label the archived copy: {e:?}").into());
+            }
+
+            for group in recordings.iter() {
+                for i in 0..group.recordings.row_count() {
+                    let mut recording = group.recordings.row_data(i).unwrap();
+                    if recording.key == key {
+                        recording.label = label.label.as_str().into();
+                        recording.note = label.note.as_str().into();
+                        group.recordings.set_row_data(i, recording);
+                    }
+                }
+            }
+        }
+    });
+    recordings_global.on_toggle_pin({
+        let active = active.clone();
+        let handle = main_window.clone();
+        move |recording| {
+            let handle = handle.unwrap();
+            let physics_inspector = active.physics_inspector();
+            let result = active.archive().and_then(|archive| {
+                match (recording.archived, archive.find(&recording.key)?) {
+                    (true, _) => archive.unpin(recording.i as u32),
+                    (false, Some(archived)) => archive.unpin(archived),
+                    (false, None) => archive
+                        .pin(&physics_inspector, recording.i as u32)
+                        .map(drop),
+                }
+            });
+            if let Err(e) = result {
+                handle.set_error(format!("{e:?}").into());
+            }
+            read_recordings_update_main(handle, &active);
+        }
+    });
+    recordings_global.on_export_recordings({
+        let recordings = recordings_unfiltered.clone();
+        let active = active.clone();
+        let handle = main_window.clone();
+        move || {
+            let handle = handle.unwrap();
+            let (recent, archived) = checked_recordings(&recordings);
+            if recent.is_empty() && archived.is_empty() {
+                handle.set_error("No recordings selected".into());
+                return;
+            }
+
+            let Some(path) = rfd::FileDialog::new()
+                .set_title("Export Recordings")
+                .set_file_name(format!("recordings.{}", bundle::EXTENSION))
+                .add_filter("Atlas recordings", &[bundle::EXTENSION])
+                .save_file()
+            else {
+                return;
+            };
+
+            let result = active.archive().and_then(|archive| {
+                let physics_inspector = active.physics_inspector();
+                let recordings: Vec<_> = (recent.iter().map(|&i| (&physics_inspector, i)))
+                    .chain(archived.iter().map(|&i| (archive.physics_inspector(), i)))
+                    .collect();
+                bundle::export_bundle(&path, &recordings)
+            });
+            match result {
+                Ok(()) => handle.set_status(format!("Exported to {}", path.display()).into()),
+                Err(e) => handle.set_error(format!("{e:?}").into()),
+            }
+        }
+    });
+    recordings_global.on_import_recordings({
+        let active = active.clone();
         let handle = main_window.clone();
         move || {
-            recordings.set_vec(Vec::new());
             let handle = handle.unwrap();
+            let Some(paths) = rfd::FileDialog::new()
+                .set_title("Import Recordings")
+                .add_filter("Atlas recordings", &[bundle::EXTENSION])
+                .pick_files()
+            else {
+                return;
+            };
 
-            match read_recordings(&physics_inspector) {
+            let result = active.archive().and_then(|archive| {
+                paths.iter().try_fold(0, |count, path| {
+                    Ok(count + bundle::import_bundle(path, archive)?.len())
+                })
+            });
+            match result {
+                Ok(count) => handle.set_status(format!("Imported {count} recordings").into()),
                 Err(e) => handle.set_error(format!("{e:?}").into()),
-                Ok(new) => recordings.set_vec(new),
+            }
+            read_recordings_update_main(handle, &active);
+        }
+    });
+    recordings_global.on_delete_confirmation_text({
+        let recordings = recordings_unfiltered.clone();
+        move || {
+            let (recent, archived) = checked_recordings(&recordings);
+            let text = match (recent.len(), archived.len()) {
+                (0, 0) => "Nothing selected.".to_owned(),
+                (n, 0) => format!("Delete {n} recent recording(s)?"),
+                (n, archived) => format!(
+                    "Delete {} recording(s)? {archived} of them will be removed from the archive for good.",
+                    n + archived
+                ),
             };
+            text.into()
         }
     });
     recordings_global.on_delete_recordings({
-        let physics_inspector = celeste.physics_inspector();
         let recordings = Rc::clone(&recordings_unfiltered);
+        let active = active.clone();
         let handle = main_window.clone();
         move || {
             let handle = handle.unwrap();
-            if let Err(e) = physics_inspector.delete_recent_recordings() {
-                handle.set_error(format!("{e:?}").into());
+            let physics_inspector = active.physics_inspector();
+            let (recent, archived) = checked_recordings(&recordings);
+            if recent.is_empty() && archived.is_empty() {
+                handle.set_error("Nothing selected".into());
+                return;
             }
-            match read_recordings(&physics_inspector) {
-                Err(e) => handle.set_error(format!("{e:?}").into()),
-                Ok(new) => recordings.set_vec(new),
+            let result = atlas_core::recordings::delete_recordings(&physics_inspector, &recent)
+                .and_then(|()| {
+                    let archive = active.archive()?;
+                    archived.iter().try_for_each(|&i| archive.unpin(i))
+                });
+            if let Err(e) = result {
+                handle.set_error(format!("{e:?}").into());
             }
+            reload_recordings(&handle, &recordings, &active);
         }
     });
     recordings_global.on_set_filter({
-        let filter_model = filter_model.clone();
-        move |filter| filtered_recordings::set_filter(&filter, &*filter_model)
+        let recordings = recordings_unfiltered.clone();
+        move |filter| filtered_recordings::set_filter(&filter, &recordings)
     });
+
+    split_table::setup(
+        &recordings_global,
+        main_window,
+        recordings_unfiltered,
+        active,
+    );
 }
 
-pub fn read_recordings_update_main(handle: MainWindow, physics_inspector: &PhysicsInspector) {
-    let model = handle.get_recordings();
-    let model = filtered_recordings::get_source_vec_model(&model);
-    match read_recordings(physics_inspector) {
-        Err(e) => handle.set_error(format!("{e:?}").into()),
-        Ok(new) => model.set_vec(new),
+/// Where recordings are read from, CCT's recent recordings or the archive.
+fn source_physics_inspector(
+    active: &ActiveInstallation,
+    archived: bool,
+) -> Result<PhysicsInspector> {
+    match archived {
+        true => Ok(active.archive()?.physics_inspector().clone()),
+        false => Ok(active.physics_inspector()),
     }
 }
 
-pub fn read_recordings(physics_inspector: &PhysicsInspector) -> Result<Vec<MapRecordings>> {
-    let mut recent_recordings = physics_inspector.recent_recordings()?;
-    recent_recordings.sort_by_key(|a| a.0);
+/// Indices of the checked recent and archived recordings.
+fn checked_recordings(recordings: &VecModel<RecordingGroup>) -> (Vec<u32>, Vec<u32>) {
+    let mut recent = Vec::new();
+    let mut archived = Vec::new();
+    for group in recordings.iter() {
+        for recording in group.recordings.iter().filter(|rec| rec.checked) {
+            match recording.archived {
+                true => archived.push(recording.i as u32),
+                false => recent.push(recording.i as u32),
+            }
+        }
+    }
+    (recent, archived)
+}
 
-    let now = chrono::Utc::now();
+pub fn read_recordings_update_main(handle: MainWindow, active: &ActiveInstallation) {
+    let model = handle.get_recordings();
+    let model = filtered_recordings::get_source_vec_model(&model);
+    reload_recordings(&handle, model, active);
+}
 
-    let mut recordings = IndexMap::<_, Vec<_>>::new();
-    for (i, layout) in recent_recordings {
-        let old_cct = layout.map_bin.is_none();
+/// Fills the language picker with the languages of `celeste`.
+pub fn set_languages(handle: &MainWindow, celeste: &CelesteInstallation) {
+    let languages = names::languages(celeste).unwrap_or_else(|e| {
+        eprintln!("Failed to list languages: {e:?}");
+        vec![names::DEFAULT_LANGUAGE.to_owned()]
+    });
+    let languages: Vec<SharedString> = languages.into_iter().map(Into::into).collect();
+    handle
+        .global::<Recordings>()
+        .set_languages(ModelRc::new(VecModel::from(languages)));
+}
 
-        if layout.frame_count == 1 {
-            continue;
+/// Reads the recordings again and only updates the rows that changed, keeping the checked
+/// recordings checked.
+fn reload_recordings(
+    handle: &MainWindow,
+    model: &VecModel<RecordingGroup>,
+    active: &ActiveInstallation,
+) {
+    let mut new = match read_recordings(active) {
+        Ok(new) => new,
+        Err(e) => {
+            handle.set_error(format!("{e:?}").into());
+            return;
         }
+    };
 
-        let is_vanilla = layout.sid.map_or(false, |sid| sid.starts_with("Celeste/"));
-        let map_bin = layout.map_bin.unwrap_or_default();
-        let map_bin = match is_vanilla && !old_cct {
-            true => format!("Celeste/{map_bin}"),
-            false => map_bin,
-        };
-
-        let name = match layout.side_name.as_str() {
-            "A-Side" => layout.chapter_name,
-            _ => format!("{} {}", layout.chapter_name, layout.side_name),
-        };
-
-        let start_time = DateTime::parse_from_rfc3339(&layout.recording_started)
-            .map(|date| {
-                let is_today = date.date_naive() == now.date_naive();
-                if is_today {
-                    date.format("%R").to_string()
-                } else {
-                    date.format("%d.%m.%Y %R").to_string()
-                }
-            })
-            .unwrap_or_default();
-
-        let start_room = layout
-            .rooms
-            .first()
-            .map(|room| room.debug_room_name.as_str())
-            .unwrap_or_default();
-
-        recordings
-            .entry((map_bin, name))
-            .or_default()
-            .push(Recording {
-                checked: false,
-                i: i as i32,
-                start_time: start_time.into(),
-                start_room: start_room.into(),
-                frame_count: layout.frame_count as i32,
-            });
+    let checked: HashSet<_> = filtered_recordings::all_recordings(model)
+        .into_iter()
+        .filter(|recording| recording.checked)
+        .map(|recording| (recording.key, recording.archived))
+        .collect();
+    for recording in &mut new {
+        recording.checked = checked.contains(&(recording.key.clone(), recording.archived));
     }
 
-    Ok(recordings
-        .into_iter()
-        .map(|((map_bin, name), recordings)| MapRecordings {
-            map_bin: map_bin.into(),
-            chapter_name: name.into(),
-            checked: false,
-            recordings: Rc::new(VecModel::from(recordings)).into(),
-        })
-        .collect())
+    sync::sync_groups(model, arrange_recordings(handle, new));
+    recalc_selection_enabled(handle, model);
 }
 
-#[allow(unused)]
-fn recalc_compare_recordings_enabled(
-    handle: Weak<MainWindow>,
-    recordings: &VecModel<MapRecordings>,
-) {
-    return; // disable for now, doesn't work reliably yet
+/// Sorts, groups and filters the recordings as selected in the UI.
+fn arrange_recordings(handle: &MainWindow, recordings: Vec<Recording>) -> Vec<RecordingGroup> {
+    let recordings_global = handle.global::<Recordings>();
+    let sort_by = SortBy::from_name(&recordings_global.get_sort_by()).unwrap_or_default();
+    let group_by = GroupBy::from_name(&recordings_global.get_group_by()).unwrap_or_default();
+    let query = Rc::new(Query::parse(&recordings_global.get_filter()));
+    arrange::arrange(recordings, sort_by, group_by, &query)
+}
 
-    let main_window = handle.unwrap();
+/// Replaces CCT's chapter names with the ones in the language of `map_names`, where the map has one.
+fn localize_chapter_names(map_names: &mut MapNames, maps: &mut [MapGroup]) {
+    if let Err(e) = map_names.resolve(maps.iter().map(|map| map.map_bin.as_str())) {
+        eprintln!("Failed to look up map names: {e:?}");
+    }
+    for map in maps {
+        if let Some(name) = map_names.chapter_name(&map.map_bin, &map.side_name) {
+            map.chapter_name = name;
+        }
+    }
+}
 
-    let mut all_exactly_two = true;
-    let mut any_two = false;
+/// Reads CCT's recent recordings followed by the archived ones.
+fn read_recordings(active: &ActiveInstallation) -> Result<Vec<Recording>> {
+    let now = chrono::Utc::now();
 
-    for map in recordings.iter() {
-        let n_checked = map.recordings.iter().filter(|rec| rec.checked).count();
+    let mut maps = atlas_core::recordings::read_recordings(
+        &active.physics_inspector(),
+        active.archive().ok(),
+    )?;
+    localize_chapter_names(&mut active.map_names(), &mut maps);
 
-        if n_checked == 0 {
-            continue;
-        }
+    Ok(maps
+        .into_iter()
+        .flat_map(|map| {
+            map.recordings
+                .into_iter()
+                .map(|recording| {
+                    let start_time = recording
+                        .recording_started
+                        .map(|date| {
+                            let is_today = date.date_naive() == now.date_naive();
+                            if is_today {
+                                date.format("%R").to_string()
+                            } else {
+                                date.format("%d.%m.%Y %R").to_string()
+                            }
+                        })
+                        .unwrap_or_default();
+
+                    Recording {
+                        checked: false,
+                        i: recording.index as i32,
+                        key: recording.key.into(),
+                        map_bin: map.map_bin.as_str().into(),
+                        chapter_name: map.chapter_name.as_str().into(),
+                        archived: map.archived,
+                        started: recording
+                            .recording_started
+                            .map(|date| date.to_utc().to_rfc3339())
+                            .unwrap_or_default()
+                            .into(),
+                        start_time: start_time.into(),
+                        start_room: recording.start_room.into(),
+                        rooms: Rc::new(VecModel::from(
+                            recording
+                                .rooms
+                                .into_iter()
+                                .map(Into::into)
+                                .collect::<Vec<_>>(),
+                        ))
+                        .into(),
+                        date: recording
+                            .recording_started
+                            .map(|date| date.format("%Y-%m-%d").to_string())
+                            .unwrap_or_default()
+                            .into(),
+                        frame_count: recording.frame_count as i32,
+                        source: recording
+                            .provenance
+                            .map(|provenance| provenance.describe())
+                            .unwrap_or_default()
+                            .into(),
+                        label: recording.label.label.into(),
+                        note: recording.label.note.into(),
+                        pinned: recording.pinned,
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect())
+}
+
+/// Deleting needs a checked recording, comparing times at least two checked recordings of the
+/// same map.
+fn recalc_selection_enabled(handle: &MainWindow, recordings: &VecModel<RecordingGroup>) {
+    let any_checked = recordings
+        .iter()
+        .any(|group| group.recordings.iter().any(|rec| rec.checked));
+    let compare_enabled = checked_per_map(recordings)
+        .values()
+        .any(|recordings| recordings.len() >= 2);
+    let global = handle.global::<Recordings>();
+    global.set_delete_recordings_enabled(any_checked);
+    global.set_compare_recordings_enabled(compare_enabled);
+}
 
-        if n_checked == 2 {
-            any_two = true;
-        } else {
-            all_exactly_two = false;
+/// Checked recordings by map bin, skipping ones of old CCT versions which don't know their map.
+fn checked_per_map(
+    recordings: &VecModel<RecordingGroup>,
+) -> IndexMap<SharedString, Vec<Recording>> {
+    let mut maps: IndexMap<_, Vec<_>> = IndexMap::new();
+    for group in recordings.iter() {
+        for rec in group.recordings.iter() {
+            if rec.checked && !rec.map_bin.is_empty() {
+                maps.entry(rec.map_bin.clone()).or_default().push(rec);
+            }
         }
     }
-
-    main_window
-        .global::<Recordings>()
-        .set_compare_recordings_enabled(any_two && all_exactly_two);
+    maps
 }
 
 fn compare_recordings(
     handle: Weak<MainWindow>,
-    recordings_unfiltered: &VecModel<MapRecordings>,
-    celeste: &CelesteInstallation,
+    recordings_unfiltered: &VecModel<RecordingGroup>,
+    active: &ActiveInstallation,
+    comparisons: Arc<Mutex<Vec<MapTimesave>>>,
 ) {
-    let mut maps: IndexMap<String, Vec<u32>> = IndexMap::new();
-    for map in recordings_unfiltered.iter() {
-        if map.chapter_name.is_empty() {
-            continue;
-        };
-
-        for rec in map.recordings.iter() {
-            if rec.checked {
-                maps.entry(map.map_bin.to_string())
-                    .or_default()
-                    .push(rec.i as u32);
-            }
+    let main_window = handle.unwrap();
+    let recordings_global = main_window.global::<Recordings>();
+    let baseline_key = recordings_global.get_baseline_key();
+    let baseline_archived = recordings_global.get_baseline_archived();
+
+    let archive = match active.archive() {
+        Ok(archive) => archive,
+        Err(e) => {
+            main_window.set_error(format!("{e:?}").into());
+            return;
         }
+    };
+    let physics_inspector = active.physics_inspector();
+
+    let (maps, single): (Vec<_>, Vec<_>) = checked_per_map(recordings_unfiltered)
+        .into_iter()
+        .partition(|(_, recordings)| recordings.len() >= 2);
+    if !single.is_empty() {
+        let maps: Vec<_> = single.iter().map(|(map_bin, _)| map_bin.as_str()).collect();
+        main_window.set_error(
+            format!(
+                "Skipped {}, check a second recording to compare",
+                maps.join(", ")
+            )
+            .into(),
+        );
     }
+    let maps: Vec<_> = maps
+        .into_iter()
+        .map(|(map_bin, recordings)| TimesaveSelection {
+            map_name: match recordings[0].chapter_name.is_empty() {
+                true => map_bin.to_string(),
+                false => recordings[0].chapter_name.to_string(),
+            },
+            map_bin: map_bin.into(),
+            baseline: recordings
+                .iter()
+                .position(|rec| rec.key == baseline_key && rec.archived == baseline_archived),
+            recordings: recordings
+                .iter()
+                .map(|rec| TimesaveRecording {
+                    physics_inspector: match rec.archived {
+                        true => archive.physics_inspector().clone(),
+                        false => physics_inspector.clone(),
+                    },
+                    index: rec.i as u32,
+                    name: recording_name(rec),
+                })
+                .collect(),
+        })
+        .collect();
 
-    let celeste = celeste.clone();
     std::thread::spawn(move || {
-        let result = (|| -> Result<_> {
-            let mut renders = Vec::new();
-
-            for (map_bin, recordings) in maps {
-                let (map, archive) = celeste.find_map_by_map_bin(&map_bin)?;
-
-                let map_name = archive
-                    .map(|mut archive| -> Result<_> {
-                        let dialog = archive.get_dialog("English")?;
-                        let map_name = dialog.get(&map_bin).unwrap_or(&map_bin);
-                        Ok(map_name.to_owned())
-                    })
-                    .transpose()?
-                    .unwrap_or_else(|| map_bin.clone());
-
-                if recordings.len() != 2 {
-                    continue;
-                }
-
-                let a = celesteloader::cct_physics_inspector::compare_timesave::compare_timesave(
-                    &celeste.physics_inspector(),
-                    &map,
-                    &map_name,
-                    (recordings[0], recordings[1]),
-                )?;
-                renders.push(a);
-            }
-
-            Ok(renders)
-        })();
+        let result = atlas_core::compare::compare_timesave(&maps);
 
         handle
             .upgrade_in_event_loop(move |handle| match result {
-                Ok(renderings) => {
-                    let text = renderings.join("\n\n");
-
-                    let clip_msg = match copypasta::ClipboardContext::new()
-                        .and_then(|mut clip| clip.set_contents(text.clone()))
-                    {
-                        Ok(()) => "Copied to clipboard".into(),
-                        Err(e) => format!("Failed to copy to clipboard: {e}"),
-                    };
-
-                    handle.set_compare_timesave_text(format!("{text}\n{clip_msg}").into());
+                Ok(result) => {
+                    let mut comparisons = comparisons.lock().unwrap();
+                    *comparisons = result;
+                    show_timesave(&handle, &comparisons);
                 }
                 Err(e) => {
                     handle.set_error(format!("{e:?}").into());
@@ -332,3 +618,38 @@ fn compare_recordings(
             .unwrap();
     });
 }
+
+/// Shows the comparisons in the format picked in the popup and copies them to the clipboard.
+fn show_timesave(handle: &MainWindow, comparisons: &[MapTimesave]) {
+    let format = TimesaveFormat::from_name(&handle.global::<Recordings>().get_timesave_format())
+        .unwrap_or(TimesaveFormat::Text);
+    let text = match compare::export_timesave(comparisons, format) {
+        Ok(text) => text,
+        Err(e) => {
+            handle.set_error(format!("{e:?}").into());
+            return;
+        }
+    };
+
+    let clip_msg = match copypasta::ClipboardContext::new()
+        .and_then(|mut clip| clip.set_contents(text.clone()))
+    {
+        Ok(()) => "Copied to clipboard".into(),
+        Err(e) => format!("Failed to copy to clipboard: {e}"),
+    };
+
+    handle.set_compare_timesave_text(format!("{text}\n{clip_msg}").into());
+}
+
+/// How a recording is called in comparisons, like `#3 (archived) new route`.
+fn recording_name(recording: &Recording) -> String {
+    let mut name = format!("#{}", recording.i);
+    if recording.archived {
+        name.push_str(" (archived)");
+    }
+    if !recording.label.is_empty() {
+        name.push(' ');
+        name.push_str(&recording.label);
+    }
+    name
+}
@@ -0,0 +1,243 @@
+//! Filter queries for the recordings list, like `room:a-01 frames:<3000 date:today label:"best"`.
+//!
+//! Terms are separated by spaces and all have to match. Text without a key is searched for in the
+//! chapter, map, label, note, start room and source of a recording. Prefixing a term with `-`
+//! negates it. Terms with unknown values are ignored, so that half-typed queries don't hide everything.
+
+use std::cmp::Ordering;
+
+use chrono::{Duration, Local};
+use slint::Model;
+
+use crate::Recording;
+
+#[derive(Default, Debug, PartialEq)]
+pub struct Query {
+    terms: Vec<(bool, Term)>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Term {
+    Text(String),
+    /// Any room visited by the recording
+    Room(String),
+    Frames(Comparison, i32),
+    /// `YYYY-MM-DD`, or a prefix of it
+    Date(Comparison, String),
+    Label(String),
+    Note(String),
+    Chapter(String),
+    Map(String),
+    Source(String),
+    Vanilla(bool),
+    Archived(bool),
+    Pinned(bool),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Comparison {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl Comparison {
+    fn split(value: &str) -> (Comparison, &str) {
+        for (prefix, comparison) in [
+            ("<=", Comparison::Le),
+            (">=", Comparison::Ge),
+            ("<", Comparison::Lt),
+            (">", Comparison::Gt),
+            ("=", Comparison::Eq),
+        ] {
+            if let Some(rest) = value.strip_prefix(prefix) {
+                return (comparison, rest);
+            }
+        }
+        (Comparison::Eq, value)
+    }
+
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Lt => ordering.is_lt(),
+            Comparison::Le => ordering.is_le(),
+            Comparison::Eq => ordering.is_eq(),
+            Comparison::Ge => ordering.is_ge(),
+            Comparison::Gt => ordering.is_gt(),
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Query {
+        let terms = tokenize(query)
+            .into_iter()
+            .filter_map(|token| {
+                let (negated, token) = match token.strip_prefix('-') {
+                    Some(token) if !token.is_empty() => (true, token),
+                    _ => (false, token.as_str()),
+                };
+                Some((negated, parse_term(token)?))
+            })
+            .collect();
+        Query { terms }
+    }
+
+    pub fn matches(&self, recording: &Recording) -> bool {
+        self.terms
+            .iter()
+            .all(|(negated, term)| term.matches(recording) != *negated)
+    }
+}
+
+/// Splits at whitespace, keeping quoted parts like `label:"new route"` together.
+fn tokenize(query: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn parse_term(token: &str) -> Option<Term> {
+    let Some((key, value)) = token.split_once(':') else {
+        return Some(Term::Text(token.to_lowercase()));
+    };
+    let text = || value.to_lowercase();
+    let bool = || match value.to_lowercase().as_str() {
+        "yes" | "true" | "1" => Some(true),
+        "no" | "false" | "0" => Some(false),
+        _ => None,
+    };
+
+    Some(match key.to_lowercase().as_str() {
+        "room" => Term::Room(text()),
+        "frames" => {
+            let (comparison, value) = Comparison::split(value);
+            Term::Frames(comparison, value.parse().ok()?)
+        }
+        "date" => {
+            let (comparison, value) = Comparison::split(value);
+            let today = Local::now().date_naive();
+            let date = match value.to_lowercase().as_str() {
+                "today" => today.to_string(),
+                "yesterday" => (today - Duration::days(1)).to_string(),
+                date if !date.is_empty()
+                    && date.chars().all(|c| c.is_ascii_digit() || c == '-') =>
+                {
+                    date.to_owned()
+                }
+                _ => return None,
+            };
+            Term::Date(comparison, date)
+        }
+        "label" => Term::Label(text()),
+        "note" => Term::Note(text()),
+        "chapter" => Term::Chapter(text()),
+        "map" => Term::Map(text()),
+        "source" | "tas" => Term::Source(text()),
+        "vanilla" => Term::Vanilla(bool()?),
+        "archived" => Term::Archived(bool()?),
+        "pinned" => Term::Pinned(bool()?),
+        _ => Term::Text(token.to_lowercase()),
+    })
+}
+
+fn contains(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(needle)
+}
+
+impl Term {
+    fn matches(&self, recording: &Recording) -> bool {
+        match self {
+            Term::Text(text) => [
+                &recording.chapter_name,
+                &recording.map_bin,
+                &recording.label,
+                &recording.note,
+                &recording.start_room,
+                &recording.source,
+            ]
+            .iter()
+            .any(|field| contains(field, text)),
+            Term::Room(room) => recording
+                .rooms
+                .iter()
+                .any(|visited| visited.to_lowercase() == *room),
+            Term::Frames(comparison, frames) => comparison.holds(recording.frame_count.cmp(frames)),
+            Term::Date(comparison, date) => {
+                !recording.date.is_empty()
+                    && match comparison {
+                        Comparison::Eq => recording.date.starts_with(date.as_str()),
+                        comparison => comparison.holds(recording.date.as_str().cmp(date)),
+                    }
+            }
+            Term::Label(label) => contains(&recording.label, label),
+            Term::Note(note) => contains(&recording.note, note),
+            Term::Chapter(chapter) => contains(&recording.chapter_name, chapter),
+            Term::Map(map_bin) => contains(&recording.map_bin, map_bin),
+            Term::Source(source) => contains(&recording.source, source),
+            Term::Vanilla(vanilla) => recording.map_bin.starts_with("Celeste/") == *vanilla,
+            Term::Archived(archived) => recording.archived == *archived,
+            Term::Pinned(pinned) => recording.pinned == *pinned,
+        }
+    }
+}
+
+#[test]
+fn parse() {
+    assert_eq!(
+        Query::parse(r#"room:a-01 frames:<3000 label:"Best Route" -vanilla:no frames:abc"#),
+        Query {
+            terms: vec![
+                (false, Term::Room("a-01".into())),
+                (false, Term::Frames(Comparison::Lt, 3000)),
+                (false, Term::Label("best route".into())),
+                (true, Term::Vanilla(false)),
+            ]
+        }
+    );
+    assert_eq!(Query::parse("  "), Query::default());
+}
+
+#[test]
+fn matches() {
+    use std::rc::Rc;
+
+    let recording = Recording {
+        map_bin: "Celeste/1-ForsakenCity".into(),
+        chapter_name: "Forsaken City".into(),
+        frame_count: 2500,
+        date: Local::now().date_naive().to_string().into(),
+        label: "best".into(),
+        rooms: Rc::new(slint::VecModel::from(vec!["a-00".into(), "a-01".into()])).into(),
+        ..Default::default()
+    };
+
+    let matches = |query: &str| Query::parse(query).matches(&recording);
+    assert!(matches(""));
+    assert!(matches("forsaken"));
+    assert!(matches(
+        r#"room:A-01 frames:<3000 date:today label:"best" vanilla:yes"#
+    ));
+    assert!(matches("date:>=2020-01-01 -archived:yes"));
+    assert!(!matches("room:a-02"));
+    assert!(!matches("frames:>=3000"));
+    assert!(!matches("date:yesterday"));
+    assert!(!matches("-label:best"));
+}
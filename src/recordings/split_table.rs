@@ -0,0 +1,170 @@
+//! The split table pane, showing the time a recording spent in each room, optionally next to
+//! another recording of the same map.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use atlas_core::splits::{room_splits, RoomSplit, SplitTable};
+use copypasta::ClipboardProvider;
+use slint::{ComponentHandle, ModelRc, StandardListViewItem, TableColumn, VecModel, Weak};
+
+use super::{filtered_recordings, recording_name, source_physics_inspector};
+use crate::installations::ActiveInstallation;
+use crate::{MainWindow, Recording, RecordingGroup, Recordings};
+
+#[derive(Default)]
+struct SplitsState {
+    recording: Option<(Recording, Vec<RoomSplit>)>,
+    /// Other recordings of the same map, in the order of `split-candidates` after "None"
+    candidates: Vec<Recording>,
+    table: Option<SplitTable>,
+}
+
+pub fn setup(
+    recordings_global: &Recordings<'_>,
+    main_window: Weak<MainWindow>,
+    recordings_unfiltered: Rc<VecModel<RecordingGroup>>,
+    active: ActiveInstallation,
+) {
+    let state = Rc::new(RefCell::new(SplitsState::default()));
+
+    recordings_global.on_show_splits({
+        let state = state.clone();
+        let active = active.clone();
+        let handle = main_window.clone();
+        move |recording| {
+            let handle = handle.unwrap();
+            let splits = match read_splits(&active, &recording) {
+                Ok(splits) => splits,
+                Err(e) => {
+                    handle.set_error(format!("{e:?}").into());
+                    return false;
+                }
+            };
+
+            let candidates: Vec<_> = filtered_recordings::all_recordings(&recordings_unfiltered)
+                .into_iter()
+                .filter(|other| {
+                    other.key != recording.key
+                        && other.map_bin == recording.map_bin
+                        && other.chapter_name == recording.chapter_name
+                })
+                .collect();
+
+            let recordings_global = handle.global::<Recordings>();
+            recordings_global.set_splits_title(
+                format!(
+                    "{} in {}",
+                    recording_name(&recording),
+                    recording.chapter_name
+                )
+                .into(),
+            );
+            let names = std::iter::once("None".into())
+                .chain(candidates.iter().map(|other| recording_name(other).into()))
+                .collect::<Vec<_>>();
+            recordings_global.set_split_candidates(ModelRc::new(VecModel::from(names)));
+
+            let mut state = state.borrow_mut();
+            state.candidates = candidates;
+            state.recording = Some((recording, splits));
+            show_table(&handle, &mut state, None);
+            true
+        }
+    });
+    recordings_global.on_compare_splits({
+        let state = state.clone();
+        let handle = main_window.clone();
+        move |candidate| {
+            let handle = handle.unwrap();
+            let mut state = state.borrow_mut();
+            let other = usize::try_from(candidate - 1)
+                .ok()
+                .and_then(|i| state.candidates.get(i).cloned());
+
+            let other = match other
+                .map(|other| read_splits(&active, &other).map(|splits| (other, splits)))
+                .transpose()
+            {
+                Ok(other) => other,
+                Err(e) => {
+                    handle.set_error(format!("{e:?}").into());
+                    None
+                }
+            };
+            show_table(&handle, &mut state, other);
+        }
+    });
+    recordings_global.on_copy_splits({
+        let handle = main_window.clone();
+        move |format| {
+            let handle = handle.unwrap();
+            let state = state.borrow();
+            let Some(table) = &state.table else {
+                return;
+            };
+
+            let text = match format.as_str() {
+                "CSV" => table.to_csv(),
+                _ => table.to_markdown(),
+            };
+            match copypasta::ClipboardContext::new().and_then(|mut clip| clip.set_contents(text)) {
+                Ok(()) => handle.set_status(format!("Copied splits as {format}").into()),
+                Err(e) => handle.set_error(format!("Failed to copy to clipboard: {e}").into()),
+            }
+        }
+    });
+}
+
+fn read_splits(
+    active: &ActiveInstallation,
+    recording: &Recording,
+) -> anyhow::Result<Vec<RoomSplit>> {
+    let physics_inspector = source_physics_inspector(active, recording.archived)?;
+    room_splits(&physics_inspector, recording.i as u32)
+}
+
+/// Shows the splits of the selected recording, next to `other` if given.
+fn show_table(
+    handle: &MainWindow,
+    state: &mut SplitsState,
+    other: Option<(Recording, Vec<RoomSplit>)>,
+) {
+    let Some((recording, splits)) = &state.recording else {
+        return;
+    };
+    let table = match other {
+        Some((other, other_splits)) => SplitTable::side_by_side(
+            &recording_name(recording),
+            splits,
+            &recording_name(&other),
+            &other_splits,
+        ),
+        None => SplitTable::single(splits),
+    };
+
+    let columns: Vec<_> = table
+        .headers
+        .iter()
+        .map(|header| {
+            let mut column = TableColumn::default();
+            column.title = header.into();
+            column.horizontal_stretch = 1.0;
+            column
+        })
+        .collect();
+    let rows: Vec<ModelRc<StandardListViewItem>> = table
+        .rows
+        .iter()
+        .map(|row| {
+            let cells: Vec<StandardListViewItem> =
+                row.iter().map(|cell| cell.as_str().into()).collect();
+            ModelRc::new(VecModel::from(cells))
+        })
+        .collect();
+
+    let recordings_global = handle.global::<Recordings>();
+    recordings_global.set_split_columns(ModelRc::new(VecModel::from(columns)));
+    recordings_global.set_split_rows(ModelRc::new(VecModel::from(rows)));
+    state.table = Some(table);
+}
@@ -0,0 +1,108 @@
+//! Incremental updates of the recordings list, so reloads don't reset rows in the UI that
+//! didn't change.
+
+use slint::{Model, VecModel};
+
+use super::filtered_recordings::get_source_recordings;
+use crate::{Recording, RecordingGroup};
+
+/// Updates `model` to `new` group by group and recording by recording.
+pub fn sync_groups(model: &VecModel<RecordingGroup>, new: Vec<RecordingGroup>) {
+    sync_rows(
+        model,
+        new,
+        |group| group.id.clone(),
+        |old, new| {
+            let recordings_changed = sync_rows(
+                &get_source_recordings(&old.recordings),
+                get_source_recordings(&new.recordings).iter().collect(),
+                |recording| (recording.key.clone(), recording.archived),
+                |old, new| (!same_recording(old, &new)).then_some(new),
+            );
+            let new = RecordingGroup {
+                recordings: old.recordings.clone(),
+                ..new
+            };
+            // also when only the recordings changed, so the filter re-checks if the group has visible ones
+            (recordings_changed || new != *old).then_some(new)
+        },
+    );
+}
+
+/// The rooms of a recording never change, and are a fresh model every time they are read.
+fn same_recording(old: &Recording, new: &Recording) -> bool {
+    let old = Recording {
+        rooms: new.rooms.clone(),
+        ..old.clone()
+    };
+    old == *new
+}
+
+/// Removes, moves and inserts rows of `model` until it matches `new`. Rows are matched by `id`,
+/// `update` gets a matched row and its new version and returns `None` if it didn't change.
+/// Returns whether anything changed.
+fn sync_rows<T: Clone + 'static, K: PartialEq>(
+    model: &VecModel<T>,
+    new: Vec<T>,
+    id: impl Fn(&T) -> K,
+    update: impl Fn(&T, T) -> Option<T>,
+) -> bool {
+    let mut changed = false;
+
+    let new_ids: Vec<K> = new.iter().map(&id).collect();
+    for i in (0..model.row_count()).rev() {
+        if !new_ids.contains(&id(&model.row_data(i).unwrap())) {
+            model.remove(i);
+            changed = true;
+        }
+    }
+
+    for (j, (new, new_id)) in new.into_iter().zip(new_ids).enumerate() {
+        let existing = (j..model.row_count()).find(|&k| id(&model.row_data(k).unwrap()) == new_id);
+        let Some(k) = existing else {
+            model.insert(j, new);
+            changed = true;
+            continue;
+        };
+
+        let old = match k == j {
+            true => model.row_data(j).unwrap(),
+            false => {
+                let old = model.remove(k);
+                model.insert(j, old.clone());
+                changed = true;
+                old
+            }
+        };
+        if let Some(updated) = update(&old, new) {
+            model.set_row_data(j, updated);
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+#[test]
+fn sync() {
+    let model = VecModel::from(vec![(1, 'a'), (2, 'b'), (3, 'c')]);
+    let update = |old: &(i32, char), new: (i32, char)| (*old != new).then_some(new);
+
+    assert!(sync_rows(
+        &model,
+        vec![(0, 'z'), (1, 'a'), (3, 'C'), (2, 'b')],
+        |row| row.0,
+        update
+    ));
+    assert_eq!(
+        model.iter().collect::<Vec<_>>(),
+        [(0, 'z'), (1, 'a'), (3, 'C'), (2, 'b')]
+    );
+
+    assert!(!sync_rows(
+        &model,
+        model.iter().collect(),
+        |row| row.0,
+        update
+    ));
+}
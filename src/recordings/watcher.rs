@@ -6,16 +6,15 @@ use notify_debouncer_full::{
     notify::{self, RecommendedWatcher},
     DebounceEventResult, Debouncer, RecommendedCache,
 };
-use slint::Weak;
+use slint::{ComponentHandle, Weak};
 
-use crate::MainWindow;
+use crate::{MainWindow, Recordings};
 
 pub fn start_watcher(
     physics_inspector: &PhysicsInspector,
     watcher_handle: Weak<MainWindow>,
 ) -> Result<Debouncer<RecommendedWatcher, RecommendedCache>> {
     let recent_recordings_path = physics_inspector.recent_recordings.clone();
-    let physics_inspector = physics_inspector.clone();
 
     let mut last_event = Instant::now();
 
@@ -31,7 +30,7 @@ pub fn start_watcher(
                     .flat_map(|event| &event.event.paths)
                     .any(|path| {
                         path.to_str()
-                            .map_or(false, |e| e.ends_with("_room-layout.json"))
+                            .is_some_and(|e| e.ends_with("_room-layout.json"))
                     });
 
             if room_layout_changed {
@@ -44,10 +43,10 @@ pub fn start_watcher(
                 let since_last = now.duration_since(last_event);
                 last_event = now;
 
-                let physics_inspector = physics_inspector.clone();
                 let result = watcher_handle.upgrade_in_event_loop(move |handle| {
                     let start_reading = Instant::now();
-                    super::read_recordings_update_main(handle, &physics_inspector);
+                    // the active installation can't be sent to this thread, but the callback knows it
+                    handle.global::<Recordings>().invoke_refresh_recordings();
 
                     println!(
                         "reloading room layouts, {:.02}s after last, took {}ms",
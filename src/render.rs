@@ -1,33 +1,116 @@
-use annotate_celeste_map::{ColorMode, LineSettings};
+use annotate_celeste_map::LineSettings;
 use anyhow::{Context, Result};
-use celesteloader::{cct_physics_inspector::PhysicsInspector, map::Map, CelesteInstallation};
-use celesterender::asset::{AssetDb, ModLookup};
-use celesterender::{CelesteRenderData, Layer, RenderMapSettings, RenderResult};
+use atlas_core::archive::Archive;
+use atlas_core::render::{self, OutputFormat, RenderOptions, RenderRegion, RenderState};
+use celesteloader::cct_physics_inspector::PhysicsInspector;
+use celesteloader::CelesteInstallation;
+use celesterender::Layer;
 use indexmap::IndexMap;
 use slint::{ComponentHandle, FilterModel, Model, VecModel, Weak};
-use std::fmt::Write;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
 use std::time::Instant;
-use std::{collections::HashSet, panic::AssertUnwindSafe, rc::Rc};
 
-use crate::{MainWindow, MapRecordings, Render};
+use crate::installations::ActiveInstallation;
+use crate::settings::{self, Settings};
+use crate::{MainWindow, RecordingGroup, Render, RenderSettings};
+
+/// The checked recordings of one map
+struct MapSelection {
+    map_bin: String,
+    chapter_name: String,
+    archived: bool,
+    recordings: Vec<u32>,
+    labels: HashMap<u32, String>,
+}
+
+/// Where and how rendered maps get saved
+struct RenderOutput {
+    format: OutputFormat,
+    frame_stride: u32,
+    dir: PathBuf,
+    file_name_template: String,
+    open: bool,
+}
 
 pub fn setup(
     render_global: Render<'_>,
     main_window: Weak<MainWindow>,
-    filter_model: &Rc<FilterModel<Rc<VecModel<MapRecordings>>, impl Fn(&MapRecordings) -> bool>>,
-    celeste: CelesteInstallation,
+    filter_model: &Rc<FilterModel<Rc<VecModel<RecordingGroup>>, impl Fn(&RecordingGroup) -> bool>>,
+    active: ActiveInstallation,
+    settings: &Settings,
 ) {
+    render_global.set_output_dir(
+        settings
+            .render_output_dir
+            .as_ref()
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .unwrap_or_default()
+            .into(),
+    );
+    render_global.set_file_name_template(settings.render_file_name.as_str().into());
+    render_global.set_open_after_render(settings.open_after_render);
+    render_global.set_render_settings(render_settings_to_ui(&settings.render));
+
+    render_global.on_pick_output_dir({
+        let handle = main_window.clone();
+        move || {
+            let handle = handle.unwrap();
+            let render_global = handle.global::<Render>();
+
+            let Some(dir) = rfd::FileDialog::new()
+                .set_title("Pick Render Output Folder")
+                .pick_folder()
+            else {
+                return;
+            };
+            render_global.set_output_dir(dir.to_string_lossy().as_ref().into());
+            render_global.invoke_output_settings_changed();
+        }
+    });
+    render_global.on_output_settings_changed({
+        let handle = main_window.clone();
+        move || {
+            let handle = handle.unwrap();
+            let render_global = handle.global::<Render>();
+
+            let output_dir = render_global.get_output_dir();
+            let result = settings::update_settings(|settings| {
+                settings.render_output_dir =
+                    (!output_dir.is_empty()).then(|| PathBuf::from(output_dir.as_str()));
+                settings.render_file_name = render_global.get_file_name_template().into();
+                settings.open_after_render = render_global.get_open_after_render();
+            });
+            if let Err(e) = result {
+                handle.set_error(format!("Failed to save settings: {e:?}").into());
+            }
+        }
+    });
+
     render_global.on_render({
         let recordings = filter_model.clone();
         let handle = main_window.clone();
 
         move |settings| {
-            let color_mode = match settings.color_mode.as_str() {
-                "Gradient" => ColorMode::Gradient,
-                "Random" => ColorMode::Random,
-                "State" => ColorMode::State,
-                "Red" => ColorMode::Color([255, 0, 0, 255]),
-                _ => unreachable!(),
+            let render_settings = render_settings_from_ui(&settings);
+            if let Err(e) = settings::update_settings(|settings| settings.render = render_settings)
+            {
+                eprintln!("Failed to save render settings: {e:?}");
+            }
+
+            let Some(color_mode) = render::color_mode_from_name(&settings.color_mode) else {
+                handle
+                    .unwrap()
+                    .set_error(format!("Unknown color mode {}", settings.color_mode).into());
+                return;
+            };
+            let region = match RenderRegion::parse(&settings.region) {
+                Ok(region) => region,
+                Err(e) => {
+                    handle.unwrap().set_error(format!("{e:?}").into());
+                    return;
+                }
             };
 
             let layer = [
@@ -49,37 +132,73 @@ pub fn setup(
                 },
             );
 
-            let map_bins: IndexMap<(String, String), Vec<_>> = recordings
-                .iter()
-                .filter_map(|map| {
-                    let key = (map.map_bin.to_string(), map.chapter_name.to_string());
-                    let recordings: Vec<_> = map
-                        .recordings
-                        .iter()
-                        .filter_map(|rec| rec.checked.then_some(rec.i))
-                        .collect();
-                    (!recordings.is_empty()).then_some((key, recordings))
-                })
-                .collect();
+            // groups don't have to be maps, so the checked recordings are collected per map
+            let mut maps = IndexMap::new();
+            for group in recordings.iter() {
+                for rec in group.recordings.iter().filter(|rec| rec.checked) {
+                    let map = maps
+                        .entry((rec.map_bin.clone(), rec.archived))
+                        .or_insert_with(|| MapSelection {
+                            map_bin: rec.map_bin.to_string(),
+                            chapter_name: rec.chapter_name.to_string(),
+                            archived: rec.archived,
+                            recordings: Vec::new(),
+                            labels: HashMap::new(),
+                        });
+                    map.recordings.push(rec.i as u32);
+                    if !rec.label.is_empty() {
+                        map.labels.insert(rec.i as u32, rec.label.to_string());
+                    }
+                }
+            }
+            let maps: Vec<MapSelection> = maps.into_values().collect();
 
-            if map_bins.is_empty() {
+            if maps.is_empty() {
                 handle.unwrap().set_error("No recordings selected".into());
                 return;
             }
 
-            let celeste = celeste.clone();
+            let output = {
+                let handle = handle.unwrap();
+                let render_global = handle.global::<Render>();
+                let dir = render_global.get_output_dir();
+                let file_name_template = render_global.get_file_name_template();
+                RenderOutput {
+                    format: OutputFormat::from_name(&settings.format).unwrap_or(OutputFormat::Png),
+                    frame_stride: settings.frame_stride.max(1) as u32,
+                    dir: match dir.is_empty() {
+                        true => std::env::temp_dir().join("atlas"),
+                        false => PathBuf::from(dir.as_str()),
+                    },
+                    file_name_template: match file_name_template.is_empty() {
+                        true => settings::DEFAULT_RENDER_FILE_NAME.to_owned(),
+                        false => file_name_template.into(),
+                    },
+                    open: render_global.get_open_after_render(),
+                }
+            };
+
+            let celeste = active.get();
+            let archive = active.archive().ok().cloned();
             let handle = handle.clone();
             std::thread::spawn(move || {
                 let result = render_recordings(
-                    map_bins,
+                    maps,
                     &celeste,
-                    LineSettings {
-                        width: settings.width,
-                        color_mode,
-                        anti_alias: settings.anti_alias,
+                    archive.as_ref(),
+                    RenderOptions {
+                        line_settings: LineSettings {
+                            width: settings.width,
+                            color_mode,
+                            anti_alias: settings.anti_alias,
+                        },
+                        layer,
+                        only_include_visited_rooms: settings.only_render_visited,
+                        compare: settings.compare,
+                        region,
+                        region_padding: settings.region_padding.max(0) as u32,
                     },
-                    layer,
-                    settings.only_render_visited,
+                    &output,
                     |status_update| {
                         handle
                             .upgrade_in_event_loop(|handle| {
@@ -110,45 +229,56 @@ pub fn setup(
     });
 }
 
-struct RenderState {
-    celeste: CelesteInstallation,
-    physics_inspector: PhysicsInspector,
-    asset_db: AssetDb<ModLookup>,
-    render_data: CelesteRenderData,
-}
-impl RenderState {
-    fn new(celeste: &CelesteInstallation) -> Result<Self> {
-        Ok(RenderState {
-            celeste: celeste.clone(),
-            physics_inspector: PhysicsInspector::new(celeste),
-            asset_db: AssetDb::new(ModLookup::all_mods(celeste)?),
-            render_data: CelesteRenderData::base(celeste)?,
-        })
+pub fn render_settings_to_ui(settings: &settings::RenderSettings) -> RenderSettings {
+    let layer = &settings.layer;
+    RenderSettings {
+        width: settings.width,
+        only_render_visited: settings.only_render_visited,
+        color_mode: settings.color_mode.as_str().into(),
+        anti_alias: settings.anti_alias,
+        compare: settings.compare,
+        layer: crate::Layer {
+            fgtiles: layer.fgtiles,
+            bgtiles: layer.bgtiles,
+            entities: layer.entities,
+            fgdecals: layer.fgdecals,
+            bgdecals: layer.bgdecals,
+        },
+        format: settings.format.as_str().into(),
+        frame_stride: settings.frame_stride,
+        region: settings.region.as_str().into(),
+        region_padding: settings.region_padding as i32,
     }
+}
 
-    fn render(
-        &mut self,
-        map_bin: &str,
-        settings: RenderMapSettings,
-    ) -> Result<(RenderResult, Map)> {
-        catch(|| {
-            celesterender::render_map_bin(
-                &self.celeste,
-                &mut self.render_data,
-                &mut self.asset_db,
-                map_bin,
-                settings,
-            )
-        })
+pub fn render_settings_from_ui(settings: &RenderSettings) -> settings::RenderSettings {
+    let layer = &settings.layer;
+    settings::RenderSettings {
+        width: settings.width,
+        only_render_visited: settings.only_render_visited,
+        color_mode: settings.color_mode.to_string(),
+        anti_alias: settings.anti_alias,
+        compare: settings.compare,
+        layer: settings::LayerSettings {
+            fgtiles: layer.fgtiles,
+            bgtiles: layer.bgtiles,
+            entities: layer.entities,
+            fgdecals: layer.fgdecals,
+            bgdecals: layer.bgdecals,
+        },
+        format: settings.format.to_string(),
+        frame_stride: settings.frame_stride.max(1),
+        region: settings.region.trim().to_owned(),
+        region_padding: settings.region_padding.max(0) as u32,
     }
 }
 
 fn render_recordings(
-    map_bins: IndexMap<(String, String), Vec<i32>>,
+    maps: Vec<MapSelection>,
     celeste: &CelesteInstallation,
-    line_settings: LineSettings,
-    layer: Layer,
-    mut only_include_visited_rooms: bool,
+    archive: Option<&Archive>,
+    options: RenderOptions,
+    output: &RenderOutput,
     on_status_update: impl Fn(String),
     on_error: impl Fn(anyhow::Error),
 ) -> Result<()> {
@@ -156,85 +286,86 @@ fn render_recordings(
 
     let mut state = RenderState::new(celeste)?;
 
-    for ((map_bin, name), recordings) in map_bins.into_iter().rev() {
-        let visited_rooms = if only_include_visited_rooms {
-            cct_visited_rooms(&recordings, &state.physics_inspector).unwrap_or_else(|e| {
-                eprintln!("Couldn't read room layouts, falling back to including all rooms: {e}");
-                only_include_visited_rooms = false;
-                Default::default()
-            })
-        } else {
-            HashSet::new()
-        };
-
+    for map in maps.into_iter().rev() {
+        let MapSelection {
+            map_bin,
+            chapter_name: name,
+            archived,
+            recordings,
+            labels,
+        } = map;
         if let Err(e) = (|| -> Result<()> {
-            let render_settings = RenderMapSettings {
-                layer,
-                include_room: &|room| {
-                    !only_include_visited_rooms
-                        || visited_rooms.contains(room.name.trim_start_matches("lvl_"))
-                },
-                status_update: &|room_current, room_total| {
-                    on_status_update(format!("Rendering [{room_current}/{room_total}]"))
-                },
+            let physics_inspector = match archived {
+                true => archive
+                    .context("the archive of pinned recordings couldn't be opened")?
+                    .physics_inspector()
+                    .clone(),
+                false => PhysicsInspector::new(celeste),
             };
+            state.set_recordings_source(physics_inspector);
 
-            let start_render = Instant::now();
-            let (mut result, _map) = state
-                .render(&map_bin, render_settings)
-                .with_context(|| format!("failed to render {name}"))?;
-
-            // let size_filled = map.rooms.iter().map(|room| room.bounds.area()).sum::<f32>();
-            // let size = result.bounds.area();
-            // let density = size_filled / size;
+            std::fs::create_dir_all(&output.dir)?;
+            let out_path = output.dir.join(render::expand_file_name_template(
+                &output.file_name_template,
+                &map_bin,
+                &name,
+                &recordings,
+                &labels,
+                chrono::Local::now(),
+                output.format,
+            ));
 
-            // let width = Some(2.0);
-            // let width = width.unwrap_or_else(|| if density > 0.5 { 8.0 } else { 3.0 });
+            match output.format {
+                OutputFormat::Png => {
+                    let mut result = render::render_map(
+                        &mut state,
+                        &map_bin,
+                        &name,
+                        &recordings,
+                        &labels,
+                        options.clone(),
+                        &on_status_update,
+                    )?;
 
-            on_status_update("Annotating...".into());
-            let start_annotate = Instant::now();
-            annotate_celeste_map::annotate_cct_recording_skia(
-                &mut result.image,
-                &state.physics_inspector,
-                recordings.into_iter().map(|i| i as u32),
-                result.bounds,
-                line_settings,
-            )?;
+                    on_status_update("Encoding...".into());
+                    let start_encode = Instant::now();
+                    result.save_png(&out_path, celesterender::Compression::Balanced)?;
 
-            on_status_update("Encoding...".into());
-            let start_encode = Instant::now();
-            let tmp = std::env::temp_dir().join("atlas");
-            std::fs::create_dir_all(&tmp)?;
-            let out_path = tmp.join(format!("{}.png", map_bin.replace(['/'], "_")));
-            result.save_png(&out_path, celesterender::Compression::Balanced)?;
-
-            let end = Instant::now();
-
-            println!(
-                "Rendered map {map_bin} in {:.2}ms render {:.2}ms annotate {:.2}ms encode",
-                (start_annotate - start_render).as_millis(),
-                (start_encode - start_annotate).as_millis(),
-                (end - start_encode).as_millis(),
-            );
-
-            if !result.unknown_entities.is_empty() {
-                let mut unknown = result.unknown_entities.iter().collect::<Vec<_>>();
-                unknown.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
-
-                eprintln!(
-                    "  Found {} unknown entities: ({} ...)",
-                    unknown.len(),
-                    unknown
-                        .iter()
-                        .take(5)
-                        .fold(String::new(), |mut acc, (num, name)| {
-                            let _ = write!(&mut acc, "{num} {name} ");
-                            acc
-                        })
-                );
+                    println!(
+                        "Encoded map {map_bin} to {} in {:.2}ms",
+                        out_path.display(),
+                        start_encode.elapsed().as_millis()
+                    );
+                }
+                OutputFormat::Svg => {
+                    let svg = render::render_map_svg(
+                        &mut state,
+                        &map_bin,
+                        &name,
+                        &recordings,
+                        options.clone(),
+                        &on_status_update,
+                    )?;
+                    std::fs::write(&out_path, svg)?;
+                }
+                OutputFormat::Gif | OutputFormat::Apng | OutputFormat::Frames => {
+                    atlas_core::animation::render_animation(
+                        &mut state,
+                        &map_bin,
+                        &name,
+                        &recordings,
+                        options.clone(),
+                        output.frame_stride,
+                        output.format,
+                        &out_path,
+                        &on_status_update,
+                    )?;
+                }
             }
 
-            opener::open(&out_path)?;
+            if output.open {
+                opener::open(&out_path)?;
+            }
             Ok(())
         })() {
             eprintln!("{e:?}");
@@ -244,31 +375,3 @@ fn render_recordings(
 
     Ok(())
 }
-
-fn cct_visited_rooms(
-    recordings: &[i32],
-    physics_inspector: &PhysicsInspector,
-) -> Result<HashSet<String>> {
-    let mut rooms = HashSet::new();
-    for &recording in recordings {
-        let layout = physics_inspector.room_layout(recording as u32)?;
-        rooms.extend(layout.rooms.into_iter().map(|room| room.debug_room_name));
-    }
-    Ok(rooms)
-}
-
-fn catch<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
-    match std::panic::catch_unwind(AssertUnwindSafe(f)) {
-        Ok(Ok(val)) => Ok(val),
-        Ok(Err(val)) => Err(val),
-        Err(e) => {
-            if let Some(s) = e.downcast_ref::<String>() {
-                Err(anyhow::anyhow!("panic: {s}"))
-            } else if let Some(s) = e.downcast_ref::<&str>() {
-                Err(anyhow::anyhow!("panic: {s}"))
-            } else {
-                Err(anyhow::anyhow!("panicked"))
-            }
-        }
-    }
-}
@@ -1,10 +1,174 @@
 use anyhow::Result;
+use atlas_core::render::{OutputFormat, RenderRegion};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
-#[derive(Serialize, Deserialize, Default)]
+/// Version of the settings schema, bump this and add a migration to [`read_settings`]
+/// when fields are renamed or change their meaning. Added fields just need a default.
+const SETTINGS_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
-    pub celeste_path: Option<PathBuf>,
+    /// Settings files from before the schema was versioned don't have this field
+    #[serde(default)]
+    pub version: u32,
+    /// Only read to migrate settings from before multiple installations were supported
+    #[serde(skip_serializing)]
+    celeste_path: Option<PathBuf>,
+    /// Index into `installations`
+    pub active_installation: usize,
+    /// Where rendered maps are saved, a folder in the temp dir if unset
+    pub render_output_dir: Option<PathBuf>,
+    /// See [`atlas_core::render::expand_file_name_template`]
+    pub render_file_name: String,
+    pub open_after_render: bool,
+    /// Name of an `arrange::SortBy` of the recordings list
+    pub recordings_sort_by: String,
+    /// Name of an `arrange::GroupBy` of the recordings list
+    pub recordings_group_by: String,
+    /// Language of the map and chapter names, see [`atlas_core::names`]
+    pub language: String,
+
+    pub installations: Vec<Installation>,
+    pub render: RenderSettings,
+    pub record: RecordSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            version: SETTINGS_VERSION,
+            celeste_path: None,
+            active_installation: 0,
+            render_output_dir: None,
+            render_file_name: DEFAULT_RENDER_FILE_NAME.to_owned(),
+            open_after_render: true,
+            recordings_sort_by: "Index".into(),
+            recordings_group_by: "Map".into(),
+            language: atlas_core::names::DEFAULT_LANGUAGE.to_owned(),
+            installations: Vec::new(),
+            render: RenderSettings::default(),
+            record: RecordSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn active_installation(&self) -> Option<&Installation> {
+        self.installations.get(self.active_installation)
+    }
+
+    /// Makes the installation at `path` the active one, adding it if it isn't known yet.
+    pub fn activate_installation_path(&mut self, path: PathBuf) {
+        if let Some(i) = self.installations.iter().position(|inst| inst.path == path) {
+            self.active_installation = i;
+            return;
+        }
+
+        let base_name = path
+            .file_name()
+            .map_or_else(|| "Celeste".into(), |name| name.to_string_lossy());
+        let mut name = base_name.to_string();
+        let mut n = 2;
+        while self.installations.iter().any(|inst| inst.name == name) {
+            name = format!("{base_name} ({n})");
+            n += 1;
+        }
+
+        self.installations.push(Installation { name, path });
+        self.active_installation = self.installations.len() - 1;
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Installation {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+pub const DEFAULT_RENDER_FILE_NAME: &str = "{map_bin}_{date}_{time}.png";
+
+/// Persisted version of the `RenderSettings` in `render_settings.slint`
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RenderSettings {
+    pub width: f32,
+    pub only_render_visited: bool,
+    pub color_mode: String,
+    pub anti_alias: bool,
+    pub compare: bool,
+    pub layer: LayerSettings,
+    /// Name of an [`atlas_core::render::OutputFormat`]
+    pub format: String,
+    /// Game frames per frame of animated formats, signed so that a negative value in a
+    /// hand-edited file falls back to the default instead of failing to read all settings
+    pub frame_stride: i32,
+    /// See [`atlas_core::render::RenderRegion::parse`], the whole map if empty
+    pub region: String,
+    pub region_padding: u32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 2.0,
+            only_render_visited: true,
+            color_mode: "State".into(),
+            anti_alias: true,
+            compare: false,
+            layer: LayerSettings::default(),
+            format: "PNG".into(),
+            frame_stride: 3,
+            region: String::new(),
+            region_padding: 16,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LayerSettings {
+    pub fgtiles: bool,
+    pub bgtiles: bool,
+    pub entities: bool,
+    pub fgdecals: bool,
+    pub bgdecals: bool,
+}
+
+impl Default for LayerSettings {
+    fn default() -> Self {
+        LayerSettings {
+            fgtiles: true,
+            bgtiles: true,
+            entities: true,
+            fgdecals: true,
+            bgdecals: true,
+        }
+    }
+}
+
+/// Persisted version of the `RecordTasSettings` in `record_tas.slint`
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RecordSettings {
+    pub fastforward_speed: f32,
+    pub run_as_merged: bool,
+    pub record_git_tree: bool,
+    pub only_record_changes: bool,
+    pub enable_tas_recorder: bool,
+}
+
+impl Default for RecordSettings {
+    fn default() -> Self {
+        RecordSettings {
+            fastforward_speed: 500.0,
+            run_as_merged: false,
+            record_git_tree: false,
+            only_record_changes: false,
+            enable_tas_recorder: false,
+        }
+    }
 }
 
 fn settings_dir() -> PathBuf {
@@ -21,17 +185,129 @@ pub fn read_settings() -> Result<Settings> {
     }
 
     let contents = std::fs::read_to_string(settings_file)?;
-    let settings = toml::from_str(&contents)?;
+    let mut settings: Settings = toml::from_str(&contents)?;
+    migrate(&mut settings);
+    validate(&mut settings);
 
     Ok(settings)
 }
-pub fn write_settings(settings: Settings) -> Result<()> {
+
+/// Replaces values the UI can't show, e.g. after editing settings.toml by hand, with their default.
+fn validate(settings: &mut Settings) {
+    let render = &mut settings.render;
+    let default = RenderSettings::default();
+    if render.width.is_nan() || render.width <= 0.0 {
+        eprintln!("invalid line width `{}` in settings.toml", render.width);
+        render.width = default.width;
+    }
+    if atlas_core::render::color_mode_from_name(&render.color_mode).is_none() {
+        eprintln!(
+            "unknown color mode `{}` in settings.toml",
+            render.color_mode
+        );
+        render.color_mode = default.color_mode;
+    }
+    if OutputFormat::from_name(&render.format).is_none() {
+        eprintln!("unknown render format `{}` in settings.toml", render.format);
+        render.format = default.format;
+    }
+    if !(1..=u16::MAX as i32).contains(&render.frame_stride) {
+        eprintln!(
+            "invalid frame stride `{}` in settings.toml",
+            render.frame_stride
+        );
+        render.frame_stride = default.frame_stride;
+    }
+    if let Err(e) = RenderRegion::parse(&render.region) {
+        eprintln!("invalid region `{}` in settings.toml: {e}", render.region);
+        render.region = default.region;
+    }
+}
+fn migrate(settings: &mut Settings) {
+    if settings.version > SETTINGS_VERSION {
+        eprintln!(
+            "settings.toml was written by a newer version of Atlas (schema {}), unknown settings will be lost",
+            settings.version
+        );
+    }
+
+    // version 2 replaced `celeste_path` with a list of installations
+    if settings.version < 2 {
+        if let Some(path) = settings.celeste_path.take() {
+            settings.activate_installation_path(path);
+        }
+    }
+
+    settings.version = SETTINGS_VERSION;
+}
+
+pub fn write_settings(settings: &Settings) -> Result<()> {
     let dir = settings_dir();
     std::fs::create_dir_all(&dir)?;
     std::fs::write(
         dir.join(SETTINGS_FILE_NAME),
-        toml::to_string_pretty(&settings)?,
+        toml::to_string_pretty(settings)?,
     )?;
 
     Ok(())
 }
+
+/// Reads the settings, applies `f` and writes them back.
+pub fn update_settings(f: impl FnOnce(&mut Settings)) -> Result<()> {
+    let mut settings = read_settings()?;
+    f(&mut settings);
+    write_settings(&settings)
+}
+
+#[test]
+fn read_unversioned_settings() {
+    let mut settings: Settings = toml::from_str(
+        r#"
+celeste_path = "/games/Celeste"
+
+[render]
+width = 4.0
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(settings.version, 0);
+    migrate(&mut settings);
+    assert_eq!(settings.version, SETTINGS_VERSION);
+    assert_eq!(
+        settings.active_installation(),
+        Some(&Installation {
+            name: "Celeste".into(),
+            path: PathBuf::from("/games/Celeste")
+        })
+    );
+    assert_eq!(settings.render.width, 4.0);
+    assert_eq!(settings.render.color_mode, "State");
+    assert_eq!(settings.record.fastforward_speed, 500.0);
+
+    let written = toml::to_string_pretty(&settings).unwrap();
+    let reread: Settings = toml::from_str(&written).unwrap();
+    assert_eq!(reread.render.width, 4.0);
+    assert_eq!(reread.installations, settings.installations);
+}
+
+#[test]
+fn validate_hand_edited_settings() {
+    let mut settings: Settings = toml::from_str(
+        r#"
+[render]
+color_mode = "Rainbow"
+format = "WebP"
+frame_stride = -2
+region = "a-00.."
+"#,
+    )
+    .unwrap();
+
+    validate(&mut settings);
+    let default = RenderSettings::default();
+    assert_eq!(settings.render.color_mode, default.color_mode);
+    assert_eq!(settings.render.format, default.format);
+    assert_eq!(settings.render.frame_stride, default.frame_stride);
+    assert_eq!(settings.render.region, default.region);
+}